@@ -0,0 +1,10 @@
+#!/bin/sh
+# downloads the community rom database and its licence into data/chip-8-database,
+# set CHIP8_DATABASE_VERSION to a tag or commit to pin it
+set -eu
+version="${CHIP8_DATABASE_VERSION:-master}"
+base="https://raw.githubusercontent.com/chip-8/chip-8-database/$version"
+out="$(dirname "$0")/chip-8-database"
+for file in database/programs.json database/sha1-hashes.json LICENSE; do
+    curl --fail --silent --show-error --location --output "$out/$(basename "$file")" "$base/$file"
+done
@@ -0,0 +1,60 @@
+// assembles a source file into a rom, see the assembler module for the syntax
+use chip8::assembler::assemble_file;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: chip8-asm <source> [-o ROM]
+
+  -o, --output ROM   where to write the rom, default is the source with a .ch8 extension
+  --help             show this";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let mut source_path = None;
+    let mut output_path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output_path = Some(path.clone()),
+                None => usage_error("--output needs a path"),
+            },
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option {}", arg)),
+            _ if source_path.is_none() => source_path = Some(arg.clone()),
+            _ => usage_error(&format!("unexpected argument {}", arg)),
+        }
+    }
+    let source_path = source_path.unwrap_or_else(|| usage_error("no source given"));
+    let output_path = output_path.unwrap_or_else(|| {
+        Path::new(&source_path)
+            .with_extension("ch8")
+            .display()
+            .to_string()
+    });
+    if Path::new(&output_path) == Path::new(&source_path) {
+        usage_error("the rom would overwrite the source");
+    }
+
+    let rom = match assemble_file(&source_path) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+    if let Err(error) = fs::write(&output_path, &rom) {
+        eprintln!("{}: {}", output_path, error);
+        process::exit(1);
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,605 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// assembles the mnemonics the disassembler prints back into a rom. one statement per
+// line, ; starts a comment and case does not matter outside of strings:
+//
+//   start:  LD V0, 0x1F        labels end in a colon and can share a line
+//   SPEED = 4                  constants, `SPEED equ 4` works too
+//           db 0xF0, 0b1001, "hi"
+//           dw start + 2       big endian words
+//           org 0x300          skips forward, padding with zeros
+//           include "font.asm" relative to the including file
+//
+// numbers are decimal, 0x/# or $ hex and 0b or % binary, and operands can add and
+// subtract numbers, labels and constants.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+const ORIGIN: usize = 0x200;
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+struct SourceLine {
+    file: String,
+    line: usize,
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    V(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    B,
+    Hf,
+    R,
+    Range(u8, u8),
+}
+
+fn strip_comment(text: &str) -> &str {
+    // a ; inside a string does not start a comment
+    let mut in_string = false;
+    for (index, character) in text.char_indices() {
+        match character {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &text[..index],
+            _ => {}
+        }
+    }
+    text
+}
+
+// splits on commas that are not inside a string
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut in_string = false;
+    for character in text.chars() {
+        match character {
+            '"' => {
+                in_string = !in_string;
+                current.push(character);
+            }
+            ',' if !in_string => operands.push(current.trim().to_string()),
+            _ => current.push(character),
+        }
+        if character == ',' && !in_string {
+            current.clear();
+        }
+    }
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+    operands
+}
+
+fn parse_v(text: &str) -> Option<u8> {
+    let text = text.to_ascii_uppercase();
+    let digit = text.strip_prefix('V')?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn parse_register(text: &str) -> Option<Register> {
+    if let Some(v) = parse_v(text) {
+        return Some(Register::V(v));
+    }
+    let upper = text.to_ascii_uppercase().replace(' ', "");
+    match upper.as_str() {
+        "I" => Some(Register::I),
+        "[I]" => Some(Register::IndirectI),
+        "DT" => Some(Register::Dt),
+        "ST" => Some(Register::St),
+        "K" => Some(Register::K),
+        "F" => Some(Register::F),
+        "B" => Some(Register::B),
+        "HF" => Some(Register::Hf),
+        "R" => Some(Register::R),
+        _ => {
+            let mut parts = upper.splitn(2, '-');
+            let first = parse_v(parts.next()?)?;
+            let last = parse_v(parts.next()?)?;
+            Some(Register::Range(first, last))
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower
+        .strip_prefix("0x")
+        .or_else(|| lower.strip_prefix('#'))
+        .or_else(|| lower.strip_prefix('$'))
+    {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+        i64::from_str_radix(binary, 2).ok()
+    } else if lower.chars().next()?.is_ascii_digit() {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn is_symbol(text: &str) -> bool {
+    let mut characters = text.chars();
+    matches!(characters.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+struct Assembler {
+    symbols: HashMap<String, i64>,
+    // names defined so far in this pass, the second pass can give a constant a new
+    // value when it was worked out from a label further down
+    defined: HashSet<String>,
+    // true on the second pass, when every label is known
+    resolving: bool,
+    address: usize,
+    output: Vec<u8>,
+}
+
+impl Assembler {
+    fn evaluate(&self, text: &str) -> Result<i64, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("missing value".to_string());
+        }
+        // terms joined by + and -, read left to right
+        let mut total = 0i64;
+        let mut sign = 1i64;
+        let mut term = String::new();
+        let mut terms = Vec::new();
+        for character in text.chars().chain(std::iter::once('+')) {
+            match character {
+                '+' | '-' if !term.trim().is_empty() => {
+                    terms.push((sign, term.trim().to_string()));
+                    term.clear();
+                    sign = if character == '-' { -1 } else { 1 };
+                }
+                '-' if term.trim().is_empty() => sign = -sign,
+                '+' if term.trim().is_empty() => {}
+                _ => term.push(character),
+            }
+        }
+        for (sign, term) in terms {
+            let value = match parse_number(&term) {
+                Some(value) => value,
+                None if is_symbol(&term) => match self.symbols.get(&term.to_ascii_lowercase()) {
+                    Some(value) => *value,
+                    // labels further down are only known on the second pass
+                    None if !self.resolving => 0,
+                    None => return Err(format!("`{}` is not defined", term)),
+                },
+                None => return Err(format!("`{}` is not a number or name", term)),
+            };
+            total = sign
+                .checked_mul(value)
+                .and_then(|value| total.checked_add(value))
+                .ok_or_else(|| "value out of range".to_string())?;
+        }
+        Ok(total)
+    }
+
+    fn value(&self, text: &str, bits: u32) -> Result<u16, String> {
+        let value = self.evaluate(text)?;
+        let max = (1i64 << bits) - 1;
+        // bytes can also be written as negative numbers, ADD V0, -1
+        if bits == 8 && (-128..0).contains(&value) {
+            return Ok((value & 0xff) as u16);
+        }
+        // on the first pass labels further down count as 0, end - start included, so
+        // the range is only checked once they are known
+        if !self.resolving {
+            return Ok((value & max) as u16);
+        }
+        if value < 0 || value > max {
+            return Err(format!("{} does not fit in {} bits", text.trim(), bits));
+        }
+        Ok(value as u16)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+        self.address += bytes.len();
+    }
+
+    fn emit_word(&mut self, word: u16) {
+        self.emit(&word.to_be_bytes());
+    }
+
+    fn statement(&mut self, text: &str) -> Result<(), String> {
+        let mut text = text.trim();
+        // any number of labels in front of the statement
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_symbol(label) {
+                break;
+            }
+            self.define(label, self.address as i64)?;
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (word, rest) = match text.find(char::is_whitespace) {
+            Some(space) => (&text[..space], text[space..].trim()),
+            None => (text, ""),
+        };
+        let rest_words: Vec<&str> = rest.split_whitespace().collect();
+        if rest.starts_with('=')
+            || rest_words
+                .first()
+                .is_some_and(|next| next.eq_ignore_ascii_case("equ"))
+        {
+            if !is_symbol(word) {
+                return Err(format!("`{}` is not a valid name", word));
+            }
+            let expression = match rest.strip_prefix('=') {
+                Some(expression) => expression,
+                None => rest[3..].trim_start(),
+            };
+            let value = self.evaluate(expression)?;
+            return self.define(word, value);
+        }
+
+        let mnemonic = word.to_ascii_uppercase();
+        let operands = split_operands(rest);
+        match mnemonic.as_str() {
+            "DB" => {
+                for operand in &operands {
+                    if let Some(string) = operand
+                        .strip_prefix('"')
+                        .and_then(|string| string.strip_suffix('"'))
+                    {
+                        self.emit(string.as_bytes());
+                    } else {
+                        let byte = self.value(operand, 8)? as u8;
+                        self.emit(&[byte]);
+                    }
+                }
+                Ok(())
+            }
+            "DW" => {
+                for operand in &operands {
+                    let word = self.value(operand, 16)?;
+                    self.emit_word(word);
+                }
+                Ok(())
+            }
+            "ORG" => {
+                let address = self.value(rest, 16)? as usize;
+                if address < self.address {
+                    return Err(format!(
+                        "org {:#x} is before the current address {:#x}",
+                        address, self.address
+                    ));
+                }
+                let padding = vec![0; address - self.address];
+                self.emit(&padding);
+                Ok(())
+            }
+            _ => self.instruction(&mnemonic, &operands),
+        }
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        let name = name.to_ascii_lowercase();
+        if parse_register(&name).is_some() {
+            return Err(format!("`{}` is a register name", name));
+        }
+        if !self.defined.insert(name.clone()) {
+            return Err(format!("`{}` is already defined", name));
+        }
+        self.symbols.insert(name, value);
+        Ok(())
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[String]) -> Result<(), String> {
+        use Register::*;
+        let registers: Vec<Option<Register>> = operands
+            .iter()
+            .map(|operand| parse_register(operand))
+            .collect();
+        let wrong = || {
+            format!(
+                "`{} {}` is not a valid instruction",
+                mnemonic,
+                operands.join(", ")
+            )
+        };
+        let xy = |x: u8, y: u8| ((x as u16) << 8) | ((y as u16) << 4);
+        let word = match (mnemonic, registers.as_slice()) {
+            ("CLS", []) => 0x00e0,
+            ("RET", []) => 0x00ee,
+            ("SCR", []) => 0x00fb,
+            ("SCL", []) => 0x00fc,
+            ("EXIT", []) => 0x00fd,
+            ("LOW", []) => 0x00fe,
+            ("HIGH", []) => 0x00ff,
+            ("AUDIO", []) => 0xf002,
+            ("SCD", [None]) => 0x00c0 | self.value(&operands[0], 4)?,
+            ("SCU", [None]) => 0x00d0 | self.value(&operands[0], 4)?,
+            ("SYS", [None]) => self.value(&operands[0], 12)?,
+            ("JP", [None]) => 0x1000 | self.value(&operands[0], 12)?,
+            ("JP", [Some(V(0)), None]) => 0xb000 | self.value(&operands[1], 12)?,
+            ("CALL", [None]) => 0x2000 | self.value(&operands[0], 12)?,
+            ("SE", [Some(V(x)), None]) => 0x3000 | xy(*x, 0) | self.value(&operands[1], 8)?,
+            ("SNE", [Some(V(x)), None]) => 0x4000 | xy(*x, 0) | self.value(&operands[1], 8)?,
+            ("SE", [Some(V(x)), Some(V(y))]) => 0x5000 | xy(*x, *y),
+            ("SNE", [Some(V(x)), Some(V(y))]) => 0x9000 | xy(*x, *y),
+            ("LD", [Some(V(x)), None]) => 0x6000 | xy(*x, 0) | self.value(&operands[1], 8)?,
+            ("ADD", [Some(V(x)), None]) => 0x7000 | xy(*x, 0) | self.value(&operands[1], 8)?,
+            ("LD", [Some(V(x)), Some(V(y))]) => 0x8000 | xy(*x, *y),
+            ("OR", [Some(V(x)), Some(V(y))]) => 0x8001 | xy(*x, *y),
+            ("AND", [Some(V(x)), Some(V(y))]) => 0x8002 | xy(*x, *y),
+            ("XOR", [Some(V(x)), Some(V(y))]) => 0x8003 | xy(*x, *y),
+            ("ADD", [Some(V(x)), Some(V(y))]) => 0x8004 | xy(*x, *y),
+            ("SUB", [Some(V(x)), Some(V(y))]) => 0x8005 | xy(*x, *y),
+            ("SHR", [Some(V(x)), Some(V(y))]) => 0x8006 | xy(*x, *y),
+            ("SHR", [Some(V(x))]) => 0x8006 | xy(*x, *x),
+            ("SUBN", [Some(V(x)), Some(V(y))]) => 0x8007 | xy(*x, *y),
+            ("SHL", [Some(V(x)), Some(V(y))]) => 0x800e | xy(*x, *y),
+            ("SHL", [Some(V(x))]) => 0x800e | xy(*x, *x),
+            ("LD", [Some(I), None]) => {
+                let operand = operands[1].trim();
+                let long = operand.len() > 4 && operand[..4].eq_ignore_ascii_case("long");
+                if long && operand[4..].starts_with(char::is_whitespace) {
+                    let address = self.value(&operand[4..], 16)?;
+                    self.emit_word(0xf000);
+                    address
+                } else {
+                    0xa000 | self.value(operand, 12)?
+                }
+            }
+            ("RND", [Some(V(x)), None]) => 0xc000 | xy(*x, 0) | self.value(&operands[1], 8)?,
+            ("DRW", [Some(V(x)), Some(V(y)), None]) => {
+                0xd000 | xy(*x, *y) | self.value(&operands[2], 4)?
+            }
+            ("SKP", [Some(V(x))]) => 0xe09e | xy(*x, 0),
+            ("SKNP", [Some(V(x))]) => 0xe0a1 | xy(*x, 0),
+            ("LD", [Some(V(x)), Some(Dt)]) => 0xf007 | xy(*x, 0),
+            ("LD", [Some(V(x)), Some(K)]) => 0xf00a | xy(*x, 0),
+            ("LD", [Some(Dt), Some(V(x))]) => 0xf015 | xy(*x, 0),
+            ("LD", [Some(St), Some(V(x))]) => 0xf018 | xy(*x, 0),
+            ("ADD", [Some(I), Some(V(x))]) => 0xf01e | xy(*x, 0),
+            ("LD", [Some(F), Some(V(x))]) => 0xf029 | xy(*x, 0),
+            ("LD", [Some(B), Some(V(x))]) => 0xf033 | xy(*x, 0),
+            ("LD", [Some(IndirectI), Some(V(x))]) => 0xf055 | xy(*x, 0),
+            ("LD", [Some(V(x)), Some(IndirectI)]) => 0xf065 | xy(*x, 0),
+            ("LD", [Some(Hf), Some(V(x))]) => 0xf030 | xy(*x, 0),
+            ("LD", [Some(R), Some(V(x))]) => 0xf075 | xy(*x, 0),
+            ("LD", [Some(V(x)), Some(R)]) => 0xf085 | xy(*x, 0),
+            ("LD", [Some(IndirectI), Some(Range(x, y))]) => 0x5002 | xy(*x, *y),
+            ("LD", [Some(Range(x, y)), Some(IndirectI)]) => 0x5003 | xy(*x, *y),
+            ("PLANE", [None]) => 0xf001 | (self.value(&operands[0], 4)? << 8),
+            ("PITCH", [Some(V(x))]) => 0xf03a | xy(*x, 0),
+            _ => return Err(wrong()),
+        };
+        self.emit_word(word);
+        Ok(())
+    }
+}
+
+fn read_source(path: &Path, depth: usize, lines: &mut Vec<SourceLine>) -> Result<(), AsmError> {
+    let name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|error| AsmError {
+        file: name.clone(),
+        line: 0,
+        message: error.to_string(),
+    })?;
+    collect_lines(&source, &name, path.parent(), depth, lines)
+}
+
+fn collect_lines(
+    source: &str,
+    name: &str,
+    directory: Option<&Path>,
+    depth: usize,
+    lines: &mut Vec<SourceLine>,
+) -> Result<(), AsmError> {
+    for (index, text) in source.lines().enumerate() {
+        let text = strip_comment(text).trim();
+        let mut words = text.splitn(2, char::is_whitespace);
+        if words
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case("include"))
+        {
+            let error = |message: String| AsmError {
+                file: name.to_string(),
+                line: index + 1,
+                message,
+            };
+            let file = words
+                .next()
+                .map(str::trim)
+                .and_then(|file| file.strip_prefix('"'))
+                .and_then(|file| file.strip_suffix('"'))
+                .ok_or_else(|| error("include needs a file name in quotes".to_string()))?;
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(error("includes are nested too deeply".to_string()));
+            }
+            let path = match directory {
+                Some(directory) => directory.join(file),
+                None => PathBuf::from(file),
+            };
+            read_source(&path, depth + 1, lines)?;
+        } else {
+            lines.push(SourceLine {
+                file: name.to_string(),
+                line: index + 1,
+                text: text.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn assemble_lines(lines: &[SourceLine]) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        symbols: HashMap::new(),
+        defined: HashSet::new(),
+        resolving: false,
+        address: ORIGIN,
+        output: Vec::new(),
+    };
+    // the first pass only finds where every label ends up, the second one encodes
+    for resolving in [false, true] {
+        assembler.resolving = resolving;
+        assembler.address = ORIGIN;
+        assembler.output.clear();
+        assembler.defined.clear();
+        for line in lines {
+            assembler
+                .statement(&line.text)
+                .map_err(|message| AsmError {
+                    file: line.file.clone(),
+                    line: line.line,
+                    message,
+                })?;
+        }
+    }
+    Ok(assembler.output)
+}
+
+// includes are looked up relative to the current directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    collect_lines(source, "<source>", None, 0, &mut lines)?;
+    assemble_lines(&lines)
+}
+
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    read_source(path.as_ref(), 0, &mut lines)?;
+    assemble_lines(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::{disassemble, format_listing};
+    use crate::interperter::decode_opcode;
+
+    #[test]
+    fn labels_constants_and_data() {
+        let rom = assemble(
+            "; draws a face\n\
+             SIZE = 5\n\
+             start:  LD I, sprite\n\
+             \x20       DRW V0, V1, SIZE\n\
+             loop:   JP loop\n\
+             sprite: db 0b01100110, %01100110, 0, $81, 0x7E\n\
+             \x20       dw start + 2\n\
+             \x20       db \"a;b\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rom,
+            vec![
+                0xa2, 0x06, 0xd0, 0x15, 0x12, 0x04, 0x66, 0x66, 0x00, 0x81, 0x7e, 0x02, 0x02, b'a',
+                b';', b'b'
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_the_disassembler() {
+        let source = "\
+            CLS\nRET\nSYS 0x123\nJP 0x2A4\nCALL 0x300\nSE V1, 0x02\nSNE VA, 0xFF\n\
+            SE V1, V2\nLD V3, 0x1F\nADD VE, 0x01\nLD V1, V2\nOR V1, V2\nAND V1, V2\n\
+            XOR V1, V2\nADD V1, V2\nSUB V1, V2\nSHR V1, V2\nSUBN V1, V2\nSHL V1, V2\n\
+            SNE V1, V2\nLD I, 0x123\nJP V0, 0x123\nRND V5, 0x0F\nDRW V0, V1, 5\nSKP V4\n\
+            SKNP V4\nLD V2, DT\nLD V2, K\nLD DT, V2\nLD ST, V2\nADD I, V2\nLD F, V2\n\
+            LD B, V2\nLD [I], V2\nLD V2, [I]\nSCD 4\nSCR\nSCL\nEXIT\nLOW\nHIGH\n\
+            LD HF, V2\nLD R, V2\nLD V2, R\nSCU 3\nLD [I], V1-V4\nLD V1-V4, [I]\n\
+            LD I, LONG 0x1234\nPLANE 3\nAUDIO\nPITCH V2\n";
+        let rom = assemble(source).unwrap();
+        let lines = disassemble(&rom, 0x200);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, source.lines().collect::<Vec<&str>>());
+        for line in &lines {
+            assert!(decode_opcode([line.bytes[0], line.bytes[1]]).is_some());
+        }
+        // and the listing is stable when assembled a second time
+        assert_eq!(
+            format_listing(&disassemble(&assemble(&texts.join("\n")).unwrap(), 0x200)),
+            format_listing(&lines)
+        );
+    }
+
+    #[test]
+    fn forward_labels_in_expressions_and_constants() {
+        assert_eq!(
+            assemble("start: LD V0, end - start\nend:").unwrap(),
+            vec![0x60, 0x02]
+        );
+        assert_eq!(
+            assemble("N = later\nJP N\nlater: CLS").unwrap(),
+            vec![0x12, 0x02, 0x00, 0xe0]
+        );
+        assert_eq!(
+            assemble("N = 1\nN = 2").unwrap_err().message,
+            "`n` is already defined"
+        );
+    }
+
+    #[test]
+    fn errors_carry_line_numbers() {
+        let error = assemble("CLS\n\nLD V0, 0x100\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert_eq!(
+            error.to_string(),
+            "<source>:3: 0x100 does not fit in 8 bits"
+        );
+        assert_eq!(
+            assemble("JP nowhere").unwrap_err().message,
+            "`nowhere` is not defined"
+        );
+        assert_eq!(assemble("x: CLS\nx: CLS").unwrap_err().line, 2);
+        assert_eq!(
+            assemble("CLS\ndb 9223372036854775807 + 1")
+                .unwrap_err()
+                .to_string(),
+            "<source>:2: value out of range"
+        );
+        assert!(assemble("LD DT, 5").is_err());
+        assert!(assemble("org 0x100").is_err());
+    }
+
+    #[test]
+    fn include_relative_to_the_file() {
+        let directory = std::env::temp_dir().join(format!("chip8_asm_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("main.asm"),
+            "include \"data.asm\"\nJP here\n",
+        )
+        .unwrap();
+        fs::write(directory.join("data.asm"), "db 1, 2\nhere: RET\n").unwrap();
+        let rom = assemble_file(directory.join("main.asm"));
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(rom.unwrap(), vec![1, 2, 0x00, 0xee, 0x12, 0x02]);
+    }
+}
@@ -0,0 +1,372 @@
+use std::f32::consts::PI;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneSettings {
+    // in hz
+    pub frequency: f32,
+    // 0.0 is silent and 1.0 is full scale
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for ToneSettings {
+    fn default() -> ToneSettings {
+        ToneSettings {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+// produces the beep samples, kept separate from any audio device so it can be tested
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    pub settings: ToneSettings,
+    sample_rate: f32,
+    // position within the current period, 0.0 to 1.0
+    phase: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> ToneGenerator {
+        ToneGenerator {
+            settings,
+            sample_rate: sample_rate as f32,
+            phase: 0.0,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let sample = match self.settings.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+        };
+        self.phase = (self.phase + self.settings.frequency / self.sample_rate) % 1.0;
+        sample * self.settings.volume
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+// the xo chip 128 bit sample loaded by F002, played back at a rate set by FX3A
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioPattern {
+    pub bits: [u8; 16],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    // bits played per second, pitch 64 is 4000hz and every 48 steps doubles it
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    fn bit(&self, position: usize) -> bool {
+        self.bits[position / 8] & (0x80 >> (position % 8)) != 0
+    }
+}
+
+// plays an audio pattern on a loop, highest bit of the first byte first
+#[derive(Debug, Clone)]
+pub struct PatternPlayer {
+    pub pattern: AudioPattern,
+    volume: f32,
+    sample_rate: f32,
+    // position in bits within the pattern, 0.0 to 128.0
+    position: f32,
+}
+
+impl PatternPlayer {
+    pub fn new(pattern: AudioPattern, volume: f32, sample_rate: u32) -> PatternPlayer {
+        PatternPlayer {
+            pattern,
+            volume,
+            sample_rate: sample_rate as f32,
+            position: 0.0,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let sample = if self.pattern.bit(self.position as usize) {
+            self.volume
+        } else {
+            -self.volume
+        };
+        self.position = (self.position + self.pattern.playback_rate() / self.sample_rate) % 128.0;
+        sample
+    }
+}
+
+// what the audio backends actually play: the beep tone, or the xo chip pattern once a
+// rom has loaded one, and silence while the sound timer is zero
+#[derive(Debug, Clone)]
+pub struct Synth {
+    tone: ToneGenerator,
+    pattern: Option<PatternPlayer>,
+    sample_rate: u32,
+    playing: bool,
+}
+
+impl Synth {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> Synth {
+        Synth {
+            tone: ToneGenerator::new(settings, sample_rate),
+            pattern: None,
+            sample_rate,
+            playing: false,
+        }
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    pub fn set_pattern(&mut self, pattern: AudioPattern) {
+        // keep the position when nothing changed so the loop does not restart every frame
+        match &mut self.pattern {
+            Some(player) if player.pattern == pattern => {}
+            Some(player) if player.pattern.bits == pattern.bits => player.pattern = pattern,
+            _ => {
+                self.pattern = Some(PatternPlayer::new(
+                    pattern,
+                    self.tone.settings.volume,
+                    self.sample_rate,
+                ))
+            }
+        }
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if !self.playing {
+                0.0
+            } else if let Some(player) = &mut self.pattern {
+                player.next_sample()
+            } else {
+                self.tone.next_sample()
+            };
+        }
+    }
+}
+
+pub trait AudioBackend {
+    // called once per frame, playing is true while the sound timer is above zero
+    fn set_playing(&mut self, playing: bool);
+
+    // called before set_playing each frame once an xo chip rom has loaded a pattern
+    fn set_pattern(&mut self, _pattern: AudioPattern) {}
+}
+
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn set_playing(&mut self, _playing: bool) {}
+}
+
+// remembers whether the beeper was on for each frame, for headless runs and tests
+#[derive(Debug, Default)]
+pub struct RecordingAudio {
+    pub frames: Vec<bool>,
+    pub pattern: Option<AudioPattern>,
+}
+
+impl RecordingAudio {
+    pub fn new() -> RecordingAudio {
+        RecordingAudio::default()
+    }
+
+    pub fn beeps(&self) -> usize {
+        // number of times the beeper was switched on
+        let mut previous = false;
+        let mut beeps = 0;
+        for playing in &self.frames {
+            if *playing && !previous {
+                beeps += 1;
+            }
+            previous = *playing;
+        }
+        beeps
+    }
+}
+
+impl AudioBackend for RecordingAudio {
+    fn set_playing(&mut self, playing: bool) {
+        self.frames.push(playing);
+    }
+
+    fn set_pattern(&mut self, pattern: AudioPattern) {
+        self.pattern = Some(pattern);
+    }
+}
+
+// renders the audio offline, one 60th of a second per frame, so it can be written
+// out as a wav file and checked without a sound card
+pub struct WavRecorder {
+    synth: Synth,
+    sample_rate: u32,
+    // whole samples still owed from frames that did not divide evenly
+    remainder: u32,
+    pub samples: Vec<i16>,
+}
+
+impl WavRecorder {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> WavRecorder {
+        WavRecorder {
+            synth: Synth::new(settings, sample_rate),
+            sample_rate,
+            remainder: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn write_wav<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        // 16 bit mono pcm
+        let data_size = self.samples.len() as u32 * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&(self.sample_rate * 2).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl AudioBackend for WavRecorder {
+    fn set_playing(&mut self, playing: bool) {
+        self.synth.set_playing(playing);
+        let owed = self.sample_rate + self.remainder;
+        self.remainder = owed % 60;
+        let mut frame = vec![0.0; (owed / 60) as usize];
+        self.synth.fill(&mut frame);
+        self.samples
+            .extend(frame.iter().map(|sample| (sample * i16::MAX as f32) as i16));
+    }
+
+    fn set_pattern(&mut self, pattern: AudioPattern) {
+        self.synth.set_pattern(pattern);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave() {
+        let settings = ToneSettings {
+            frequency: 1000.0,
+            volume: 0.5,
+            waveform: Waveform::Square,
+        };
+        let mut generator = ToneGenerator::new(settings, 4000);
+        let mut samples = [0.0; 4];
+        generator.fill(&mut samples);
+        assert_eq!(samples, [0.5, 0.5, -0.5, -0.5]);
+    }
+
+    #[test]
+    fn triangle_wave() {
+        let settings = ToneSettings {
+            frequency: 1000.0,
+            volume: 1.0,
+            waveform: Waveform::Triangle,
+        };
+        let mut generator = ToneGenerator::new(settings, 4000);
+        let mut samples = [0.0; 4];
+        generator.fill(&mut samples);
+        assert_eq!(samples, [-1.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn pattern_playback_rate() {
+        let mut pattern = AudioPattern {
+            bits: [0; 16],
+            pitch: 64,
+        };
+        assert_eq!(pattern.playback_rate(), 4000.0);
+        pattern.pitch = 112;
+        assert_eq!(pattern.playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn pattern_bits_in_order() {
+        let mut bits = [0; 16];
+        bits[0] = 0xa0;
+        let pattern = AudioPattern { bits, pitch: 64 };
+        let mut player = PatternPlayer::new(pattern, 1.0, 4000);
+        let samples: Vec<f32> = (0..4).map(|_| player.next_sample()).collect();
+        assert_eq!(samples, [1.0, -1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn synth_silent_when_not_playing() {
+        let mut synth = Synth::new(ToneSettings::default(), 44100);
+        let mut samples = [1.0; 8];
+        synth.fill(&mut samples);
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn wav_recorder_frames() {
+        let mut recorder = WavRecorder::new(ToneSettings::default(), 44100);
+        recorder.set_playing(true);
+        recorder.set_playing(false);
+        assert_eq!(recorder.samples.len(), 2 * 735);
+        assert!(recorder.samples[..735].iter().any(|sample| *sample != 0));
+        assert!(recorder.samples[735..].iter().all(|sample| *sample == 0));
+        let mut wav = Vec::new();
+        recorder.write_wav(&mut wav).unwrap();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav.len(), 44 + 2 * 2 * 735);
+    }
+
+    #[test]
+    fn recording_counts_beeps() {
+        let mut audio = RecordingAudio::new();
+        for playing in &[false, true, true, false, true] {
+            audio.set_playing(*playing);
+        }
+        assert_eq!(audio.beeps(), 2);
+    }
+}
@@ -0,0 +1,256 @@
+// what the community chip-8 database knows about roms, looked up by their sha-1.
+// a copy is built in, see data/chip-8-database, with the test roms from
+// data/local-database over it. any files in the same schema can be parsed in its place
+use crate::display::parse_colour;
+use crate::hash::to_hex;
+use crate::json::Json;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const COMMUNITY_PROGRAMS: &str = include_str!("../data/chip-8-database/programs.json");
+const COMMUNITY_HASHES: &str = include_str!("../data/chip-8-database/sha1-hashes.json");
+const LOCAL_PROGRAMS: &str = include_str!("../data/local-database/programs.json");
+const LOCAL_HASHES: &str = include_str!("../data/local-database/sha1-hashes.json");
+
+// the settings a rom wants, everything but the title is optional
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    // instructions per frame
+    pub tickrate: Option<u32>,
+    // background then foreground, followed by the other two xo chip colours if listed
+    pub colours: Vec<[u8; 3]>,
+    // which chip8 key each direction and button is on, like ("up", 5)
+    pub keys: Vec<(String, u8)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RomDatabase {
+    programs: Vec<Json>,
+    // lowercase hex sha-1 to an index into programs
+    hashes: HashMap<String, usize>,
+}
+
+impl RomDatabase {
+    // takes the text of programs.json and sha1-hashes.json
+    pub fn parse(programs: &str, hashes: &str) -> Result<RomDatabase, String> {
+        let programs = match Json::parse(programs).map_err(|e| format!("programs: {}", e))? {
+            Json::Array(programs) => programs,
+            _ => return Err("programs: expected a list of programs".to_string()),
+        };
+        let hashes = Json::parse(hashes).map_err(|e| format!("hashes: {}", e))?;
+        let hashes = hashes
+            .as_object()
+            .ok_or_else(|| "hashes: expected an object".to_string())?
+            .iter()
+            .map(|(hash, index)| match index.as_f64() {
+                Some(index) if index >= 0.0 && (index as usize) < programs.len() => {
+                    Ok((hash.to_ascii_lowercase(), index as usize))
+                }
+                _ => Err(format!("hashes: bad program index for {}", hash)),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(RomDatabase { programs, hashes })
+    }
+
+    // the copy compiled into the crate, parsed the first time it is needed
+    pub fn bundled() -> &'static RomDatabase {
+        static DATABASE: OnceLock<RomDatabase> = OnceLock::new();
+        DATABASE.get_or_init(|| {
+            let mut database = RomDatabase::parse(COMMUNITY_PROGRAMS, COMMUNITY_HASHES)
+                .expect("the bundled rom database is broken");
+            database.extend(
+                RomDatabase::parse(LOCAL_PROGRAMS, LOCAL_HASHES)
+                    .expect("the local rom database is broken"),
+            );
+            database
+        })
+    }
+
+    // adds the roms other knows, its entries win over ones for the same rom here
+    pub fn extend(&mut self, other: RomDatabase) {
+        let offset = self.programs.len();
+        self.programs.extend(other.programs);
+        self.hashes.extend(
+            other
+                .hashes
+                .into_iter()
+                .map(|(hash, index)| (hash, index + offset)),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    pub fn lookup(&self, sha1: &[u8; 20]) -> Option<RomInfo> {
+        let hash = to_hex(sha1);
+        let program = &self.programs[*self.hashes.get(&hash)?];
+        let rom = program
+            .get("roms")
+            .and_then(|roms| roms.get(&hash))
+            .unwrap_or(&Json::Null);
+
+        let strings = |value: Option<&Json>| -> Vec<String> {
+            value
+                .and_then(Json::as_array)
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        };
+        // the first platform listed that this emulator runs
+        let (platform, quirks) = strings(rom.get("platforms"))
+            .iter()
+            .find_map(|id| {
+                let (platform, quirks) = platform_for_id(id)?;
+                let overrides = rom.get("quirkyPlatforms").and_then(|quirky| quirky.get(id));
+                Some((platform, apply_quirks(quirks, overrides)))
+            })
+            .unzip();
+        let colours = rom
+            .get("colors")
+            .and_then(|colours| colours.get("pixels"))
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|colour| colour.as_str().and_then(parse_colour))
+            .take(4)
+            .collect();
+        let keys = rom
+            .get("keys")
+            .and_then(Json::as_object)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|(name, key)| {
+                let key = key.as_f64()?;
+                (0.0..16.0)
+                    .contains(&key)
+                    .then(|| (name.clone(), key as u8))
+            })
+            .collect();
+
+        Some(RomInfo {
+            title: program
+                .get("title")
+                .and_then(Json::as_str)
+                .unwrap_or("untitled")
+                .to_string(),
+            authors: strings(program.get("authors")),
+            platform,
+            quirks,
+            tickrate: rom
+                .get("tickrate")
+                .and_then(Json::as_f64)
+                .filter(|rate| *rate >= 1.0)
+                .map(|rate| rate as u32),
+            colours,
+            keys,
+        })
+    }
+}
+
+// the database's platform ids, with the quirks it lists for each of them
+fn platform_for_id(id: &str) -> Option<(Platform, Quirks)> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks::COSMAC_VIP)),
+        "modernChip8" => Some((Platform::Chip8, Quirks::MODERN)),
+        "chip48" => Some((Platform::Chip8, Quirks::CHIP_48)),
+        "superchip1" | "superchip" => Some((Platform::SuperChip, Quirks::SUPER_CHIP)),
+        "xochip" => Some((Platform::XoChip, Quirks::XO_CHIP)),
+        // chip8x, megachip8 and friends need hardware this emulator does not have
+        _ => None,
+    }
+}
+
+// quirkyPlatforms entries name the quirks a rom needs that its platform lacks.
+// vblank and memoryIncrementByX have no quirk here and are left out
+fn apply_quirks(mut quirks: Quirks, overrides: Option<&Json>) -> Quirks {
+    let members = overrides.and_then(Json::as_object).unwrap_or(&[]);
+    for (name, value) in members {
+        let value = match value.as_bool() {
+            Some(value) => value,
+            None => continue,
+        };
+        match name.as_str() {
+            "shift" => quirks.shift_uses_vy = !value,
+            "memoryLeaveIUnchanged" => quirks.load_store_increments_i = !value,
+            "wrap" => quirks.clip_sprites = !value,
+            "jump" => quirks.jump_uses_vx = value,
+            "logic" => quirks.logic_resets_vf = value,
+            _ => {}
+        }
+    }
+    quirks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::sha1;
+
+    #[test]
+    fn bundled_database_knows_the_test_roms() {
+        let database = RomDatabase::bundled();
+        let rom = include_bytes!("../tests/roms/memory.ch8");
+        let info = database.lookup(&sha1(rom)).unwrap();
+        assert_eq!(info.title, "Memory quirk test");
+        assert_eq!(info.platform, Some(Platform::Chip8));
+        assert_eq!(info.quirks, Some(Quirks::COSMAC_VIP));
+        assert_eq!(info.tickrate, Some(15));
+        assert_eq!(info.colours, vec![[0, 0, 0], [0xff, 0xcc, 0]]);
+        assert!(database.lookup(&sha1(b"not a rom")).is_none());
+    }
+
+    // the community copy is not checked in yet, data/fetch-database.sh downloads it
+    #[test]
+    #[ignore = "data/chip-8-database is empty until data/fetch-database.sh is run"]
+    fn bundled_database_has_the_community_copy() {
+        let community = RomDatabase::parse(COMMUNITY_PROGRAMS, COMMUNITY_HASHES).unwrap();
+        assert!(community.len() > 100, "only {} roms", community.len());
+    }
+
+    #[test]
+    fn reads_the_community_schema() {
+        let programs = r##"[{
+            "title": "Game", "authors": ["Someone", "Someone Else"],
+            "roms": {"00000000000000000000000000000000000000aa": {
+                "platforms": ["megachip8", "superchip"],
+                "quirkyPlatforms": {"superchip": {"wrap": true, "shift": false, "vblank": true}},
+                "tickrate": 30,
+                "keys": {"up": 5, "a": 6, "bad": 17},
+                "colors": {"pixels": ["#112233", "#445566", "oops"], "buzzer": "#ffffff"}
+            }}
+        }]"##;
+        let hashes = r#"{"00000000000000000000000000000000000000AA": 0}"#;
+        let database = RomDatabase::parse(programs, hashes).unwrap();
+        let mut hash = [0; 20];
+        hash[19] = 0xaa;
+        let info = database.lookup(&hash).unwrap();
+        assert_eq!(info.authors, ["Someone", "Someone Else"]);
+        assert_eq!(info.platform, Some(Platform::SuperChip));
+        let quirks = info.quirks.unwrap();
+        assert!(!quirks.clip_sprites);
+        assert!(quirks.shift_uses_vy);
+        assert!(quirks.jump_uses_vx);
+        assert_eq!(info.tickrate, Some(30));
+        assert_eq!(info.keys, [("up".to_string(), 5), ("a".to_string(), 6)]);
+        assert_eq!(info.colours, [[0x11, 0x22, 0x33], [0x44, 0x55, 0x66]]);
+
+        assert!(RomDatabase::parse(programs, r#"{"aa": 3}"#).is_err());
+
+        let mut extended = RomDatabase::parse(LOCAL_PROGRAMS, LOCAL_HASHES).unwrap();
+        extended.extend(database);
+        assert_eq!(extended.len(), 4);
+        assert_eq!(extended.lookup(&hash).unwrap().title, "Game");
+    }
+}
@@ -0,0 +1,411 @@
+use crate::disassembler::disassemble_instruction;
+use crate::error::Chip8Error;
+use crate::interperter::{decode_opcode, Chip8, Opcode};
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+pub const HELP: &str = "\
+c, continue        run until a breakpoint
+p, pause           stop before the next instruction
+s, step [n]        run one (or n) instructions
+n, next            step, running a CALL through to its return
+u, until <addr>    run to an address
+b, break <addr>    set a breakpoint
+d, delete <addr>   remove a breakpoint
+bl, breakpoints    list the breakpoints
+r, regs            print the registers, I, pc and sp
+st, stack          print the stack
+t, timers          print the delay and sound timers
+m, mem <addr> [n]  print n bytes of memory, 16 by default
+h, help            print this";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    Pause,
+    Step(u32),
+    Next,
+    RunTo(u16),
+    Break(u16),
+    Delete(u16),
+    Breakpoints,
+    Registers,
+    Stack,
+    Timers,
+    Memory(u16, u16),
+    Help,
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    // addresses are hex with or without 0x, like everywhere else in chip8 land
+    u16::from_str_radix(text.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("`{}` is not a hex number", text))
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let argument = |index: usize| -> Result<u16, String> {
+            match parts.get(index) {
+                Some(text) => parse_number(text),
+                None => Err(format!("`{}` needs an address", parts[0])),
+            }
+        };
+        match parts.first().copied() {
+            Some("c") | Some("continue") => Ok(Command::Continue),
+            Some("p") | Some("pause") => Ok(Command::Pause),
+            Some("s") | Some("step") => match parts.get(1) {
+                Some(count) => count
+                    .parse()
+                    .map(Command::Step)
+                    .map_err(|_| format!("`{}` is not a number", count)),
+                None => Ok(Command::Step(1)),
+            },
+            Some("n") | Some("next") => Ok(Command::Next),
+            Some("u") | Some("until") => Ok(Command::RunTo(argument(1)?)),
+            Some("b") | Some("break") => Ok(Command::Break(argument(1)?)),
+            Some("d") | Some("delete") => Ok(Command::Delete(argument(1)?)),
+            Some("bl") | Some("breakpoints") => Ok(Command::Breakpoints),
+            Some("r") | Some("regs") => Ok(Command::Registers),
+            Some("st") | Some("stack") => Ok(Command::Stack),
+            Some("t") | Some("timers") => Ok(Command::Timers),
+            Some("m") | Some("mem") => {
+                let length = if parts.len() > 2 { argument(2)? } else { 16 };
+                Ok(Command::Memory(argument(1)?, length))
+            }
+            Some("h") | Some("help") => Ok(Command::Help),
+            Some(other) => Err(format!("unknown command `{}`, try help", other)),
+            None => Err("no command given".to_string()),
+        }
+    }
+}
+
+// sits between the frontend and Chip8::step, deciding whether each instruction runs
+#[derive(Debug, Default)]
+pub struct Debugger {
+    pub paused: bool,
+    breakpoints: BTreeSet<u16>,
+    // a breakpoint that goes away once it is hit, for next and until
+    target: Option<u16>,
+    // instructions still to run before pausing again
+    steps: u32,
+    // lets a continue move off the breakpoint it stopped on
+    resuming: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.paused || self.steps > 0
+    }
+
+    // runs the next instruction unless the debugger is holding it back. returns a
+    // message when the machine has just stopped, for the frontend to print
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<Option<String>, Chip8Error> {
+        if !self.is_running() {
+            return Ok(None);
+        }
+        let pc = chip8.pc();
+        if !self.paused && !self.resuming {
+            if self.target == Some(pc) {
+                self.stop();
+                return Ok(Some(format!("stopped at\n{}", location(chip8))));
+            }
+            if self.breakpoints.contains(&pc) {
+                self.stop();
+                return Ok(Some(format!("breakpoint\n{}", location(chip8))));
+            }
+        }
+        self.resuming = false;
+
+        if let Err(error) = chip8.step() {
+            self.stop();
+            return Err(error);
+        }
+        if self.steps > 0 {
+            self.steps -= 1;
+            if self.steps == 0 {
+                return Ok(Some(location(chip8)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn stop(&mut self) {
+        self.paused = true;
+        self.steps = 0;
+        self.target = None;
+    }
+
+    fn resume(&mut self) {
+        self.paused = false;
+        self.steps = 0;
+        self.resuming = true;
+    }
+
+    pub fn execute(&mut self, command: Command, chip8: &Chip8) -> String {
+        match command {
+            Command::Continue => {
+                self.resume();
+                "running".to_string()
+            }
+            Command::Pause => {
+                self.stop();
+                location(chip8)
+            }
+            Command::Step(count) => {
+                self.paused = true;
+                self.steps = count;
+                self.resuming = true;
+                String::new()
+            }
+            Command::Next => {
+                let opcode = chip8.fetch_opcode();
+                match decode_opcode(opcode) {
+                    Some((Opcode::CALL, _)) => {
+                        self.resume();
+                        self.target = Some(chip8.pc().wrapping_add(2));
+                    }
+                    _ => {
+                        self.paused = true;
+                        self.steps = 1;
+                        self.resuming = true;
+                    }
+                }
+                String::new()
+            }
+            Command::RunTo(address) => {
+                self.resume();
+                self.target = Some(address);
+                format!("running to {:#05x}", address)
+            }
+            Command::Break(address) => {
+                self.breakpoints.insert(address);
+                format!("breakpoint at {:#05x}", address)
+            }
+            Command::Delete(address) => {
+                if self.breakpoints.remove(&address) {
+                    format!("removed breakpoint at {:#05x}", address)
+                } else {
+                    format!("no breakpoint at {:#05x}", address)
+                }
+            }
+            Command::Breakpoints => {
+                if self.breakpoints.is_empty() {
+                    "no breakpoints".to_string()
+                } else {
+                    let addresses: Vec<String> = self
+                        .breakpoints
+                        .iter()
+                        .map(|address| format!("{:#05x}", address))
+                        .collect();
+                    addresses.join("\n")
+                }
+            }
+            Command::Registers => registers(chip8),
+            Command::Stack => {
+                if chip8.stack().is_empty() {
+                    "stack is empty".to_string()
+                } else {
+                    let frames: Vec<String> = chip8
+                        .stack()
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(index, address)| format!("{:2} {:#05x}", index, address))
+                        .collect();
+                    frames.join("\n")
+                }
+            }
+            Command::Timers => format!(
+                "delay {} sound {}",
+                chip8.delay_timer(),
+                chip8.sound_timer()
+            ),
+            Command::Memory(address, length) => memory_dump(chip8, address, length),
+            Command::Help => HELP.to_string(),
+        }
+    }
+}
+
+fn location(chip8: &Chip8) -> String {
+    match chip8.source_line(chip8.pc()) {
+        Some(line) => format!("{} (line {})", instruction_at(chip8), line),
+        None => instruction_at(chip8),
+    }
+}
+
+fn instruction_at(chip8: &Chip8) -> String {
+    let pc = chip8.pc() as usize;
+    let bytes = chip8.memory().get(pc..).unwrap_or(&[]);
+    match disassemble_instruction(bytes) {
+        Some((text, length)) => {
+            let raw: String = bytes[..length]
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            format!("{:#05x}: {} {}", pc, raw, text)
+        }
+        None if bytes.len() >= 2 => {
+            format!("{:#05x}: {:02x}{:02x} unknown", pc, bytes[0], bytes[1])
+        }
+        None => format!("{:#05x}: out of memory", pc),
+    }
+}
+
+fn registers(chip8: &Chip8) -> String {
+    let mut lines = Vec::new();
+    for row in 0..2 {
+        let row: Vec<String> = (row * 8..row * 8 + 8)
+            .map(|index| format!("v{:x}={:02x}", index, chip8.v_register[index]))
+            .collect();
+        lines.push(row.join(" "));
+    }
+    lines.push(format!(
+        "I={:#05x} pc={:#05x} sp={}",
+        chip8.index(),
+        chip8.pc(),
+        chip8.stack().len()
+    ));
+    lines.join("\n")
+}
+
+fn memory_dump(chip8: &Chip8, address: u16, length: u16) -> String {
+    let memory = chip8.memory();
+    let start = (address as usize).min(memory.len());
+    let end = (start + length as usize).min(memory.len());
+    let lines: Vec<String> = memory[start..end]
+        .chunks(16)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            format!("{:#06x}: {}", start + row * 16, bytes.join(" "))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+// reads debugger commands a line at a time on its own thread so the frontend can
+// keep drawing while it waits for the next one
+pub struct CommandReader {
+    lines: Receiver<String>,
+}
+
+impl CommandReader {
+    pub fn new<R: Read + Send + 'static>(reader: R) -> CommandReader {
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+        CommandReader { lines }
+    }
+
+    pub fn stdin() -> CommandReader {
+        CommandReader::new(std::io::stdin())
+    }
+
+    // every complete line typed since the last call
+    pub fn poll(&mut self) -> Vec<String> {
+        self.lines.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: CALL 0x208, 202: ADD V1, 1, 204: JP 0x202, 208: ADD V0, 1, 20A: RET
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::init();
+        chip8
+            .load_rom(&[
+                0x22, 0x08, 0x71, 0x01, 0x12, 0x02, 0x00, 0x00, 0x70, 0x01, 0x00, 0xee,
+            ])
+            .unwrap();
+        chip8
+    }
+
+    fn run(debugger: &mut Debugger, chip8: &mut Chip8, limit: u32) -> Option<String> {
+        for _ in 0..limit {
+            if let Some(message) = debugger.step(chip8).unwrap() {
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("s").unwrap(), Command::Step(1));
+        assert_eq!(Command::parse("step 5").unwrap(), Command::Step(5));
+        assert_eq!(Command::parse("b 0x20a").unwrap(), Command::Break(0x20a));
+        assert_eq!(Command::parse("m 300").unwrap(), Command::Memory(0x300, 16));
+        assert!(Command::parse("b").is_err());
+        assert!(Command::parse("jump").is_err());
+    }
+
+    #[test]
+    fn breakpoint_and_continue() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new();
+        debugger.execute(Command::Break(0x202), &chip8);
+        let message = run(&mut debugger, &mut chip8, 10).unwrap();
+        assert!(message.contains("0x202"));
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.v_register[0], 1);
+        // paused, nothing runs
+        assert_eq!(run(&mut debugger, &mut chip8, 10), None);
+        assert_eq!(chip8.pc(), 0x202);
+        // continuing leaves the breakpoint and stops on it again after the jump back
+        debugger.execute(Command::Continue, &chip8);
+        run(&mut debugger, &mut chip8, 10).unwrap();
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.v_register[1], 1);
+    }
+
+    #[test]
+    fn step_and_next() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new();
+        debugger.paused = true;
+        debugger.execute(Command::Step(1), &chip8);
+        run(&mut debugger, &mut chip8, 10).unwrap();
+        assert_eq!(chip8.pc(), 0x208);
+
+        let mut chip8 = self::chip8();
+        debugger.execute(Command::Next, &chip8);
+        run(&mut debugger, &mut chip8, 10).unwrap();
+        assert_eq!(chip8.pc(), 0x202);
+        assert_eq!(chip8.v_register[0], 1);
+        assert!(!debugger.is_running());
+    }
+
+    #[test]
+    fn run_to_address() {
+        let mut chip8 = chip8();
+        let mut debugger = Debugger::new();
+        debugger.execute(Command::RunTo(0x204), &chip8);
+        run(&mut debugger, &mut chip8, 10).unwrap();
+        assert_eq!(chip8.pc(), 0x204);
+        assert_eq!(
+            debugger.execute(Command::Registers, &chip8),
+            "v0=01 v1=01 v2=00 v3=00 v4=00 v5=00 v6=00 v7=00\n\
+             v8=00 v9=00 va=00 vb=00 vc=00 vd=00 ve=00 vf=00\n\
+             I=0x000 pc=0x204 sp=0"
+        );
+    }
+}
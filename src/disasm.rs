@@ -0,0 +1,50 @@
+// prints a rom as a listing of addresses, raw bytes and mnemonics
+use chip8::disassembler::{disassemble, format_listing};
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "usage: chip8-disasm <rom> [--origin ADDRESS]
+
+  --origin ADDRESS   hex address the rom is loaded at, default 200
+  --help             show this";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let mut rom_path = None;
+    let mut origin = 0x200;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => {
+                let parsed = args
+                    .next()
+                    .and_then(|value| u16::from_str_radix(value.trim_start_matches("0x"), 16).ok());
+                match parsed {
+                    Some(address) => origin = address,
+                    None => usage_error("--origin needs a hex address"),
+                }
+            }
+            _ if arg.starts_with("--") => usage_error(&format!("unknown option {}", arg)),
+            _ if rom_path.is_none() => rom_path = Some(arg.clone()),
+            _ => usage_error(&format!("unexpected argument {}", arg)),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| usage_error("no rom given"));
+    match fs::read(&rom_path) {
+        Ok(rom) => print!("{}", format_listing(&disassemble(&rom, origin))),
+        Err(error) => {
+            eprintln!("{}: {}", rom_path, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,271 @@
+use crate::interperter::{decode_opcode, Opcode};
+use std::collections::BTreeSet;
+
+// turns roms back into text using the usual chip8 mnemonics, with the super chip and
+// xo chip additions written the same way. the assembler reads this syntax back in.
+
+fn x(opcode: [u8; 2]) -> u8 {
+    opcode[0] & 0x0f
+}
+
+fn y(opcode: [u8; 2]) -> u8 {
+    opcode[1] >> 4
+}
+
+fn n(opcode: [u8; 2]) -> u8 {
+    opcode[1] & 0x0f
+}
+
+fn nnn(opcode: [u8; 2]) -> u16 {
+    u16::from_be_bytes(opcode) & 0x0fff
+}
+
+// the text of one decoded instruction. long is the word after F000, which is only
+// used by LD_IL
+pub fn mnemonic(opcode: &(Opcode, [u8; 2]), long: Option<u16>) -> String {
+    let bytes = opcode.1;
+    let (vx, vy) = (x(bytes), y(bytes));
+    match opcode.0 {
+        Opcode::CLS => "CLS".to_string(),
+        Opcode::RET => "RET".to_string(),
+        Opcode::SYS => format!("SYS 0x{:03X}", nnn(bytes)),
+        Opcode::JP_A => format!("JP 0x{:03X}", nnn(bytes)),
+        Opcode::CALL => format!("CALL 0x{:03X}", nnn(bytes)),
+        Opcode::SE_VB => format!("SE V{:X}, 0x{:02X}", vx, bytes[1]),
+        Opcode::SNE_VB => format!("SNE V{:X}, 0x{:02X}", vx, bytes[1]),
+        Opcode::SE_VV => format!("SE V{:X}, V{:X}", vx, vy),
+        Opcode::LD_VB => format!("LD V{:X}, 0x{:02X}", vx, bytes[1]),
+        Opcode::ADD_VB => format!("ADD V{:X}, 0x{:02X}", vx, bytes[1]),
+        Opcode::LD_VV => format!("LD V{:X}, V{:X}", vx, vy),
+        Opcode::OR => format!("OR V{:X}, V{:X}", vx, vy),
+        Opcode::AND => format!("AND V{:X}, V{:X}", vx, vy),
+        Opcode::XOR => format!("XOR V{:X}, V{:X}", vx, vy),
+        Opcode::ADD_VV => format!("ADD V{:X}, V{:X}", vx, vy),
+        Opcode::SUB => format!("SUB V{:X}, V{:X}", vx, vy),
+        Opcode::SHR => format!("SHR V{:X}, V{:X}", vx, vy),
+        Opcode::SUBN => format!("SUBN V{:X}, V{:X}", vx, vy),
+        Opcode::SHL => format!("SHL V{:X}, V{:X}", vx, vy),
+        Opcode::SNE_VV => format!("SNE V{:X}, V{:X}", vx, vy),
+        Opcode::LD_IA => format!("LD I, 0x{:03X}", nnn(bytes)),
+        Opcode::JP_VA => format!("JP V0, 0x{:03X}", nnn(bytes)),
+        Opcode::RND => format!("RND V{:X}, 0x{:02X}", vx, bytes[1]),
+        Opcode::DRW => format!("DRW V{:X}, V{:X}, {}", vx, vy, n(bytes)),
+        Opcode::SKP => format!("SKP V{:X}", vx),
+        Opcode::SKNP => format!("SKNP V{:X}", vx),
+        Opcode::LD_VDT => format!("LD V{:X}, DT", vx),
+        Opcode::LD_VK => format!("LD V{:X}, K", vx),
+        Opcode::LD_DTV => format!("LD DT, V{:X}", vx),
+        Opcode::LD_STV => format!("LD ST, V{:X}", vx),
+        Opcode::ADD_IV => format!("ADD I, V{:X}", vx),
+        Opcode::LD_FV => format!("LD F, V{:X}", vx),
+        Opcode::LD_BV => format!("LD B, V{:X}", vx),
+        Opcode::LD_IV => format!("LD [I], V{:X}", vx),
+        Opcode::LD_VI => format!("LD V{:X}, [I]", vx),
+        Opcode::SCD => format!("SCD {}", n(bytes)),
+        Opcode::SCR => "SCR".to_string(),
+        Opcode::SCL => "SCL".to_string(),
+        Opcode::EXIT => "EXIT".to_string(),
+        Opcode::LOW => "LOW".to_string(),
+        Opcode::HIGH => "HIGH".to_string(),
+        Opcode::LD_HFV => format!("LD HF, V{:X}", vx),
+        Opcode::LD_RV => format!("LD R, V{:X}", vx),
+        Opcode::LD_VR => format!("LD V{:X}, R", vx),
+        Opcode::SCU => format!("SCU {}", n(bytes)),
+        Opcode::LD_IVV => format!("LD [I], V{:X}-V{:X}", vx, vy),
+        Opcode::LD_VVI => format!("LD V{:X}-V{:X}, [I]", vx, vy),
+        Opcode::LD_IL => match long {
+            Some(address) => format!("LD I, LONG 0x{:04X}", address),
+            None => "LD I, LONG ?".to_string(),
+        },
+        Opcode::PLANE => format!("PLANE {}", vx),
+        Opcode::AUDIO => "AUDIO".to_string(),
+        Opcode::PITCH => format!("PITCH V{:X}", vx),
+    }
+}
+
+// decodes the instruction at the start of bytes, returning its text and length, or
+// None when the bytes are not an instruction
+pub fn disassemble_instruction(bytes: &[u8]) -> Option<(String, usize)> {
+    let opcode = decode_opcode([*bytes.first()?, *bytes.get(1)?])?;
+    if opcode.0 == Opcode::LD_IL {
+        let long = u16::from_be_bytes([*bytes.get(2)?, *bytes.get(3)?]);
+        return Some((mnemonic(&opcode, Some(long)), 4));
+    }
+    Some((mnemonic(&opcode, None), 2))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    // set when something jumps to or calls this address
+    pub label: Option<String>,
+}
+
+pub fn label_name(address: u16) -> String {
+    format!("L{:03X}", address)
+}
+
+// a straight sweep over the rom two bytes at a time. anything that does not decode is
+// written out as db so the listing always covers every byte
+pub fn disassemble(rom: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut position = 0;
+    while position < rom.len() {
+        let address = origin.wrapping_add(position as u16);
+        let (text, length) = match disassemble_instruction(&rom[position..]) {
+            Some(instruction) => instruction,
+            None => {
+                let length = (rom.len() - position).min(2);
+                let data: Vec<String> = rom[position..position + length]
+                    .iter()
+                    .map(|byte| format!("0x{:02X}", byte))
+                    .collect();
+                (format!("db {}", data.join(", ")), length)
+            }
+        };
+        lines.push(Line {
+            address,
+            bytes: rom[position..position + length].to_vec(),
+            text,
+            label: None,
+        });
+        position += length;
+    }
+
+    let targets: BTreeSet<u16> = lines
+        .iter()
+        .filter(|line| line.bytes.len() == 2)
+        .filter_map(|line| match decode_opcode([line.bytes[0], line.bytes[1]]) {
+            Some((Opcode::JP_A, bytes)) | Some((Opcode::CALL, bytes)) => Some(nnn(bytes)),
+            _ => None,
+        })
+        .collect();
+    let labelled: BTreeSet<u16> = lines
+        .iter()
+        .map(|line| line.address)
+        .filter(|address| targets.contains(address))
+        .collect();
+    for line in &mut lines {
+        if labelled.contains(&line.address) {
+            line.label = Some(label_name(line.address));
+        }
+        // jumps and calls name their target when it got a label, a target in the
+        // middle of an instruction or outside the rom keeps its address
+        if line.bytes.len() == 2 {
+            let target = match decode_opcode([line.bytes[0], line.bytes[1]]) {
+                Some((Opcode::JP_A, bytes)) => Some(("JP", nnn(bytes))),
+                Some((Opcode::CALL, bytes)) => Some(("CALL", nnn(bytes))),
+                _ => None,
+            };
+            if let Some((instruction, address)) = target.filter(|(_, a)| labelled.contains(a)) {
+                line.text = format!("{} {}", instruction, label_name(address));
+            }
+        }
+    }
+    lines
+}
+
+pub fn format_listing(lines: &[Line]) -> String {
+    let mut listing = String::new();
+    for line in lines {
+        if let Some(label) = &line.label {
+            listing += &format!("{}:\n", label);
+        }
+        let bytes: Vec<String> = line
+            .bytes
+            .chunks(2)
+            .map(|word| word.iter().map(|byte| format!("{:02X}", byte)).collect())
+            .collect();
+        listing += &format!(
+            "    0x{:03X}  {:<9}  {}\n",
+            line.address,
+            bytes.join(" "),
+            line.text
+        );
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0x00, 0xe0], "CLS"),
+            (&[0x00, 0xee], "RET"),
+            (&[0x01, 0x23], "SYS 0x123"),
+            (&[0x12, 0xa4], "JP 0x2A4"),
+            (&[0x23, 0x00], "CALL 0x300"),
+            (&[0x31, 0x02], "SE V1, 0x02"),
+            (&[0x4a, 0xff], "SNE VA, 0xFF"),
+            (&[0x51, 0x20], "SE V1, V2"),
+            (&[0x63, 0x1f], "LD V3, 0x1F"),
+            (&[0x7e, 0x01], "ADD VE, 0x01"),
+            (&[0x81, 0x20], "LD V1, V2"),
+            (&[0x81, 0x21], "OR V1, V2"),
+            (&[0x81, 0x22], "AND V1, V2"),
+            (&[0x81, 0x23], "XOR V1, V2"),
+            (&[0x81, 0x24], "ADD V1, V2"),
+            (&[0x81, 0x25], "SUB V1, V2"),
+            (&[0x81, 0x26], "SHR V1, V2"),
+            (&[0x81, 0x27], "SUBN V1, V2"),
+            (&[0x81, 0x2e], "SHL V1, V2"),
+            (&[0x91, 0x20], "SNE V1, V2"),
+            (&[0xa1, 0x23], "LD I, 0x123"),
+            (&[0xb1, 0x23], "JP V0, 0x123"),
+            (&[0xc5, 0x0f], "RND V5, 0x0F"),
+            (&[0xd0, 0x15], "DRW V0, V1, 5"),
+            (&[0xe4, 0x9e], "SKP V4"),
+            (&[0xe4, 0xa1], "SKNP V4"),
+            (&[0xf2, 0x07], "LD V2, DT"),
+            (&[0xf2, 0x0a], "LD V2, K"),
+            (&[0xf2, 0x15], "LD DT, V2"),
+            (&[0xf2, 0x18], "LD ST, V2"),
+            (&[0xf2, 0x1e], "ADD I, V2"),
+            (&[0xf2, 0x29], "LD F, V2"),
+            (&[0xf2, 0x33], "LD B, V2"),
+            (&[0xf2, 0x55], "LD [I], V2"),
+            (&[0xf2, 0x65], "LD V2, [I]"),
+            (&[0x00, 0xc4], "SCD 4"),
+            (&[0x00, 0xfb], "SCR"),
+            (&[0x00, 0xfc], "SCL"),
+            (&[0x00, 0xfd], "EXIT"),
+            (&[0x00, 0xfe], "LOW"),
+            (&[0x00, 0xff], "HIGH"),
+            (&[0xf2, 0x30], "LD HF, V2"),
+            (&[0xf2, 0x75], "LD R, V2"),
+            (&[0xf2, 0x85], "LD V2, R"),
+            (&[0x00, 0xd3], "SCU 3"),
+            (&[0x51, 0x42], "LD [I], V1-V4"),
+            (&[0x51, 0x43], "LD V1-V4, [I]"),
+            (&[0xf0, 0x00, 0x12, 0x34], "LD I, LONG 0x1234"),
+            (&[0xf3, 0x01], "PLANE 3"),
+            (&[0xf0, 0x02], "AUDIO"),
+            (&[0xf2, 0x3a], "PITCH V2"),
+        ];
+        for (bytes, text) in cases {
+            assert_eq!(
+                disassemble_instruction(bytes),
+                Some((text.to_string(), bytes.len()))
+            );
+        }
+    }
+
+    #[test]
+    fn listing_with_labels_and_data() {
+        let rom = [0x22, 0x06, 0x12, 0x02, 0x5f, 0xf1, 0x00, 0xee, 0xab];
+        let listing = format_listing(&disassemble(&rom, 0x200));
+        assert_eq!(
+            listing,
+            "    0x200  2206       CALL L206\n\
+             L202:\n    0x202  1202       JP L202\n    \
+             0x204  5FF1       db 0x5F, 0xF1\n\
+             L206:\n    0x206  00EE       RET\n    \
+             0x208  AB         db 0xAB\n"
+        );
+    }
+}
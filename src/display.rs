@@ -0,0 +1,251 @@
+use crate::hash::{adler32, crc32};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+// colours for the four values a pixel can have when both xo chip bitplanes are used
+pub const DEFAULT_PALETTE: [[u8; 3]; 4] =
+    [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+// RRGGBB with or without a leading #, as options and the rom database write colours
+pub fn parse_colour(text: &str) -> Option<[u8; 3]> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+pub trait DisplayBackend {
+    // called with the whole framebuffer, one byte per pixel and row by row,
+    // whenever the chip8 has changed it. a pixel is 0 when off, otherwise it
+    // holds a bit for each xo chip plane it is lit on
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize);
+
+    // called once per frame even when nothing changed, for backends that need to
+    // redraw or flip buffers every frame
+    fn present(&mut self) {}
+}
+
+// keeps the last frame around so tests and tools can look at it
+#[derive(Debug, Default)]
+pub struct MemoryDisplay {
+    pub gfx: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    pub frames_drawn: u64,
+}
+
+impl MemoryDisplay {
+    pub fn new() -> MemoryDisplay {
+        MemoryDisplay::default()
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.gfx[y * self.width + x] != 0
+    }
+}
+
+impl DisplayBackend for MemoryDisplay {
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize) {
+        self.gfx.clear();
+        self.gfx.extend_from_slice(gfx);
+        self.width = width;
+        self.height = height;
+        self.frames_drawn += 1;
+    }
+}
+
+pub fn write_pbm<W: Write>(
+    writer: &mut W,
+    gfx: &[u8],
+    width: usize,
+    height: usize,
+) -> io::Result<()> {
+    // plain (P1) portable bitmap, 1 is a lit pixel
+    writeln!(writer, "P1")?;
+    writeln!(writer, "{} {}", width, height)?;
+    for row in gfx.chunks(width).take(height) {
+        let line: Vec<&str> = row
+            .iter()
+            .map(|pixel| if *pixel != 0 { "1" } else { "0" })
+            .collect();
+        writeln!(writer, "{}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+pub fn write_png<W: Write>(
+    writer: &mut W,
+    gfx: &[u8],
+    width: usize,
+    height: usize,
+    palette: &[[u8; 3]; 4],
+) -> io::Result<()> {
+    // 8 bit rgb with every row unfiltered, the image data goes into stored (not
+    // compressed) deflate blocks so no compression library is needed
+    let mut pixels = Vec::with_capacity(height * (width * 3 + 1));
+    for row in gfx.chunks(width).take(height) {
+        pixels.push(0);
+        for pixel in row {
+            pixels.extend_from_slice(&palette[(*pixel & 0x03) as usize]);
+        }
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = pixels.chunks(0xffff).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        zlib.push((index + 1 == blocks.len()) as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&pixels).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_png_chunk(writer, b"IHDR", &header)?;
+    write_png_chunk(writer, b"IDAT", &zlib)?;
+    write_png_chunk(writer, b"IEND", &[])
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut checked = kind.to_vec();
+    checked.extend_from_slice(data);
+    writer.write_all(&checked)?;
+    writer.write_all(&crc32(&checked).to_be_bytes())
+}
+
+// writes every frame it is given to its own numbered pbm file in a directory
+pub struct ImageDisplay {
+    directory: PathBuf,
+    frame: u64,
+    pub last_error: Option<io::Error>,
+}
+
+impl ImageDisplay {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> ImageDisplay {
+        ImageDisplay {
+            directory: directory.into(),
+            frame: 0,
+            last_error: None,
+        }
+    }
+
+    fn write_frame(&self, gfx: &[u8], width: usize, height: usize) -> io::Result<()> {
+        let path = self.directory.join(format!("frame_{:05}.pbm", self.frame));
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_pbm(&mut writer, gfx, width, height)?;
+        writer.flush()
+    }
+}
+
+impl DisplayBackend for ImageDisplay {
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize) {
+        if let Err(error) = self.write_frame(gfx, width, height) {
+            self.last_error = Some(error);
+        }
+        self.frame += 1;
+    }
+}
+
+// draws with unicode half blocks so each character cell holds two rows of pixels
+pub struct TerminalDisplay<W: Write> {
+    writer: W,
+}
+
+impl TerminalDisplay<io::Stdout> {
+    pub fn stdout() -> TerminalDisplay<io::Stdout> {
+        TerminalDisplay::new(io::stdout())
+    }
+}
+
+impl<W: Write> TerminalDisplay<W> {
+    pub fn new(writer: W) -> TerminalDisplay<W> {
+        TerminalDisplay { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub fn terminal_frame(gfx: &[u8], width: usize, height: usize) -> String {
+    let mut frame = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let top = gfx[y * width + x] != 0;
+            let bottom = y + 1 < height && gfx[(y + 1) * width + x] != 0;
+            frame.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        frame.push('\n');
+    }
+    frame
+}
+
+impl<W: Write> DisplayBackend for TerminalDisplay<W> {
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize) {
+        // move the cursor home first so each frame overwrites the last one
+        let frame = terminal_frame(gfx, width, height);
+        let _ = write!(self.writer, "\x1b[H{}", frame);
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_display_keeps_frame() {
+        let mut display = MemoryDisplay::new();
+        let mut gfx = [0u8; 4 * 2];
+        gfx[5] = 1;
+        display.draw(&gfx, 4, 2);
+        assert!(display.pixel(1, 1));
+        assert!(!display.pixel(0, 0));
+        assert_eq!(display.frames_drawn, 1);
+    }
+
+    #[test]
+    fn pbm_output() {
+        let mut output = Vec::new();
+        write_pbm(&mut output, &[1, 0, 0, 1], 2, 2).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "P1\n2 2\n1 0\n0 1\n");
+    }
+
+    #[test]
+    fn png_output() {
+        let mut output = Vec::new();
+        write_png(&mut output, &[1, 0, 0, 1], 2, 2, &DEFAULT_PALETTE).unwrap();
+        assert_eq!(&output[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&output[12..16], b"IHDR");
+        assert_eq!(&output[16..24], &[0, 0, 0, 2, 0, 0, 0, 2]);
+        // two rows of a filter byte and two rgb pixels in a single stored block
+        let idat = 8 + 25;
+        assert_eq!(
+            &output[idat..idat + 8],
+            &[0, 0, 0, 25, b'I', b'D', b'A', b'T']
+        );
+        assert_eq!(&output[idat + 15..idat + 22], &[0, 255, 255, 255, 0, 0, 0]);
+        assert_eq!(
+            &output[output.len() - 12..output.len() - 4],
+            b"\0\0\0\0IEND"
+        );
+    }
+
+    #[test]
+    fn terminal_half_blocks() {
+        assert_eq!(terminal_frame(&[1, 0, 1, 1, 0, 1], 2, 3), "█▄\n ▀\n");
+    }
+}
@@ -0,0 +1,86 @@
+use crate::assembler::AsmError;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    // CALL with 16 calls already nested
+    StackOverflow { pc: u16 },
+    // RET with nothing on the stack
+    StackUnderflow { pc: u16 },
+    // an instruction or fetch touched memory past the end of the address space
+    MemoryOutOfRange { pc: u16, address: usize },
+    UnknownOpcode { pc: u16, opcode: [u8; 2] },
+    RomTooLarge { size: usize, max: usize },
+    // a save state that is truncated, from another version or otherwise unreadable
+    InvalidSaveState(String),
+    // a save state made while a different rom was loaded
+    SaveStateRomMismatch,
+    // a movie recorded with a different rom
+    MovieRomMismatch,
+    // an octo source rom that does not compile
+    Compile(AsmError),
+    Io(io::Error),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::StackOverflow { pc } => write!(f, "stack overflow at {:#05x}", pc),
+            Chip8Error::StackUnderflow { pc } => {
+                write!(f, "return with an empty stack at {:#05x}", pc)
+            }
+            Chip8Error::MemoryOutOfRange { pc, address } => write!(
+                f,
+                "memory access out of range ({:#x}) at {:#05x}",
+                address, pc
+            ),
+            Chip8Error::UnknownOpcode { pc, opcode } => write!(
+                f,
+                "unknown opcode {:02x}{:02x} at {:#05x}",
+                opcode[0], opcode[1], pc
+            ),
+            Chip8Error::RomTooLarge { size, max } => write!(
+                f,
+                "rom is {} bytes but only {} bytes fit in memory",
+                size, max
+            ),
+            Chip8Error::InvalidSaveState(reason) => write!(f, "invalid save state: {}", reason),
+            Chip8Error::SaveStateRomMismatch => {
+                write!(f, "save state was made with a different rom")
+            }
+            Chip8Error::MovieRomMismatch => write!(f, "movie was recorded with a different rom"),
+            Chip8Error::Compile(error) => write!(f, "{}", error),
+            Chip8Error::Io(error) => write!(f, "could not read rom: {}", error),
+        }
+    }
+}
+
+impl Chip8Error {
+    // where the running program was when the error happened
+    pub fn pc(&self) -> Option<u16> {
+        match self {
+            Chip8Error::StackOverflow { pc }
+            | Chip8Error::StackUnderflow { pc }
+            | Chip8Error::MemoryOutOfRange { pc, .. }
+            | Chip8Error::UnknownOpcode { pc, .. } => Some(*pc),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::Compile(error) => Some(error),
+            Chip8Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(error: io::Error) -> Chip8Error {
+        Chip8Error::Io(error)
+    }
+}
@@ -0,0 +1,544 @@
+use crate::interperter::{Chip8, STACK_DEPTH};
+use std::collections::BTreeSet;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+// a gdb remote serial protocol server, so gdb or anything else that speaks the protocol
+// can inspect and drive a chip8. the registers gdb sees, in order, are v0-vf, i and pc
+// (16 bits, little endian on the wire), sp, dt and st. they are described in target.xml
+// so gdb picks them up by name once connected with `target remote`.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 21;
+
+// instructions run between checks for an interrupt from gdb while continuing
+const BATCH: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Incoming {
+    Packet(String),
+    BadChecksum,
+    // ctrl-c in gdb
+    Interrupt,
+}
+
+// what the server should do with a packet once the stub has looked at it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Reply(String),
+    Continue,
+    Step,
+    // detach or kill, the reply is sent and the connection closed
+    Close(String),
+}
+
+#[derive(Debug)]
+pub struct GdbStub {
+    breakpoints: BTreeSet<u16>,
+    // the timers tick once for this many instructions while gdb has the machine running
+    pub instructions_per_frame: u32,
+    instructions: u32,
+    // why the machine last stopped with SIGILL, until it is sent to gdb
+    error: Option<String>,
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+pub fn frame_packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data))
+}
+
+impl GdbStub {
+    pub fn new(instructions_per_frame: u32) -> GdbStub {
+        GdbStub {
+            breakpoints: BTreeSet::new(),
+            instructions_per_frame,
+            instructions: 0,
+            error: None,
+        }
+    }
+
+    fn register(chip8: &Chip8, number: usize) -> Option<Vec<u8>> {
+        match number {
+            0..=15 => Some(vec![chip8.v_register[number]]),
+            16 => Some(chip8.I.to_le_bytes().to_vec()),
+            17 => Some(chip8.pc.to_le_bytes().to_vec()),
+            18 => Some(vec![chip8.sp as u8]),
+            19 => Some(vec![chip8.delay_timer]),
+            20 => Some(vec![chip8.sound_timer]),
+            _ => None,
+        }
+    }
+
+    // returns how many bytes the register took, or None when it is out of range
+    fn set_register(chip8: &mut Chip8, number: usize, bytes: &[u8]) -> Option<usize> {
+        let word = || Some(u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]));
+        match number {
+            0..=15 => chip8.v_register[number] = *bytes.first()?,
+            16 => chip8.I = word()?,
+            17 => chip8.pc = word()?,
+            18 => chip8.sp = (*bytes.first()? as u16).min(STACK_DEPTH as u16),
+            19 => chip8.delay_timer = *bytes.first()?,
+            20 => chip8.sound_timer = *bytes.first()?,
+            _ => return None,
+        }
+        Some(if number == 16 || number == 17 { 2 } else { 1 })
+    }
+
+    pub fn handle(&mut self, packet: &str, chip8: &mut Chip8) -> Action {
+        let reply = |text: &str| Action::Reply(text.to_string());
+        let error = || reply("E01");
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        match command {
+            "?" => reply("S05"),
+            "g" => {
+                let registers: Vec<u8> = (0..REGISTER_COUNT)
+                    .flat_map(|number| GdbStub::register(chip8, number).unwrap_or_default())
+                    .collect();
+                Action::Reply(hex_bytes(&registers))
+            }
+            "G" => match parse_hex_bytes(arguments) {
+                Some(bytes) => {
+                    let mut position = 0;
+                    for number in 0..REGISTER_COUNT {
+                        match GdbStub::set_register(chip8, number, &bytes[position..]) {
+                            Some(used) => position += used,
+                            None => return error(),
+                        }
+                    }
+                    reply("OK")
+                }
+                None => error(),
+            },
+            "p" => match parse_hex(arguments).and_then(|number| GdbStub::register(chip8, number)) {
+                Some(bytes) => Action::Reply(hex_bytes(&bytes)),
+                None => error(),
+            },
+            "P" => {
+                let mut parts = arguments.splitn(2, '=');
+                let number = parts.next().and_then(parse_hex);
+                let bytes = parts.next().and_then(parse_hex_bytes);
+                match (number, bytes) {
+                    (Some(number), Some(bytes)) => {
+                        match GdbStub::set_register(chip8, number, &bytes) {
+                            Some(_) => reply("OK"),
+                            None => error(),
+                        }
+                    }
+                    _ => error(),
+                }
+            }
+            "m" => {
+                let mut parts = arguments.splitn(2, ',');
+                let address = parts.next().and_then(parse_hex);
+                let length = parts.next().and_then(parse_hex);
+                match (address, length) {
+                    (Some(address), Some(length)) => {
+                        match chip8.memory.get(address..address.saturating_add(length)) {
+                            Some(bytes) => Action::Reply(hex_bytes(bytes)),
+                            None => error(),
+                        }
+                    }
+                    _ => error(),
+                }
+            }
+            "M" => {
+                let mut parts = arguments.splitn(2, ':');
+                let mut range = parts.next().unwrap_or("").splitn(2, ',');
+                let address = range.next().and_then(parse_hex);
+                let length = range.next().and_then(parse_hex);
+                let bytes = parts.next().and_then(parse_hex_bytes);
+                match (address, length, bytes) {
+                    (Some(address), Some(length), Some(bytes)) if bytes.len() == length => {
+                        match chip8
+                            .memory
+                            .get_mut(address..address.saturating_add(length))
+                        {
+                            Some(memory) => {
+                                memory.copy_from_slice(&bytes);
+                                chip8.draw_flag = true;
+                                reply("OK")
+                            }
+                            None => error(),
+                        }
+                    }
+                    _ => error(),
+                }
+            }
+            "Z" | "z" => {
+                // only software breakpoints (type 0) and hardware ones (type 1), which
+                // are the same thing on an emulator
+                let parts: Vec<&str> = arguments.split(',').collect();
+                match (parts.first(), parts.get(1).and_then(|text| parse_hex(text))) {
+                    (Some(&"0"), Some(address)) | (Some(&"1"), Some(address)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(address as u16);
+                        } else {
+                            self.breakpoints.remove(&(address as u16));
+                        }
+                        reply("OK")
+                    }
+                    _ => reply(""),
+                }
+            }
+            "c" => {
+                if let Some(address) = parse_hex(arguments) {
+                    chip8.pc = address as u16;
+                }
+                Action::Continue
+            }
+            "s" => {
+                if let Some(address) = parse_hex(arguments) {
+                    chip8.pc = address as u16;
+                }
+                Action::Step
+            }
+            "H" => reply("OK"),
+            "D" => Action::Close("OK".to_string()),
+            "k" => Action::Close(String::new()),
+            "q" => {
+                if arguments.starts_with("Supported") {
+                    reply("PacketSize=1000;qXfer:features:read+")
+                } else if arguments == "Attached" {
+                    reply("1")
+                } else if arguments == "C" {
+                    reply("QC1")
+                } else if let Some(range) = arguments.strip_prefix("Xfer:features:read:target.xml:")
+                {
+                    let mut parts = range.splitn(2, ',');
+                    let offset = parts.next().and_then(parse_hex).unwrap_or(0);
+                    let length = parts.next().and_then(parse_hex).unwrap_or(0);
+                    let start = offset.min(TARGET_XML.len());
+                    let end = (start + length).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() { "l" } else { "m" };
+                    Action::Reply(format!("{}{}", prefix, &TARGET_XML[start..end]))
+                } else {
+                    reply("")
+                }
+            }
+            // anything else is unsupported, which an empty reply tells gdb
+            _ => reply(""),
+        }
+    }
+
+    // runs one instruction and returns the stop reply if the machine stopped for a
+    // reason other than finishing the step
+    fn run_one(&mut self, chip8: &mut Chip8) -> Option<String> {
+        if let Err(error) = chip8.step() {
+            // SIGILL, and the error is shown in gdb's console before the stop
+            self.error = Some(chip8.describe_error(&error));
+            return Some("S04".to_string());
+        }
+        if chip8.is_halted() {
+            return Some("W00".to_string());
+        }
+        self.instructions += 1;
+        if self.instructions >= self.instructions_per_frame {
+            self.instructions = 0;
+            chip8.decrease_timers();
+        }
+        None
+    }
+
+    // an O packet with the error behind the last stop, gdb prints it as is
+    fn take_error_output(&mut self) -> Option<String> {
+        let error = self.error.take()?;
+        Some(format!("O{}", hex_bytes(format!("{}\n", error).as_bytes())))
+    }
+
+    fn step(&mut self, chip8: &mut Chip8) -> String {
+        self.run_one(chip8).unwrap_or_else(|| "S05".to_string())
+    }
+
+    // runs a batch of instructions, returning the stop reply when it stopped early
+    fn run(&mut self, chip8: &mut Chip8, first: bool) -> Option<String> {
+        for count in 0..BATCH {
+            // a continue moves off the breakpoint it was stopped on
+            if !(first && count == 0) && self.breakpoints.contains(&chip8.pc) {
+                return Some("S05".to_string());
+            }
+            if let Some(stop) = self.run_one(chip8) {
+                return Some(stop);
+            }
+        }
+        None
+    }
+}
+
+fn read_packets<R: Read>(reader: R, incoming: std::sync::mpsc::Sender<Incoming>) {
+    let mut bytes = BufReader::new(reader).bytes();
+    while let Some(Ok(byte)) = bytes.next() {
+        let event = match byte {
+            0x03 => Incoming::Interrupt,
+            b'$' => {
+                let mut data = Vec::new();
+                loop {
+                    match bytes.next() {
+                        Some(Ok(b'#')) => break,
+                        Some(Ok(byte)) => data.push(byte),
+                        _ => return,
+                    }
+                }
+                let sum: Vec<u8> = bytes.by_ref().take(2).filter_map(Result::ok).collect();
+                let data = String::from_utf8_lossy(&data).to_string();
+                let expected = std::str::from_utf8(&sum)
+                    .ok()
+                    .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+                if expected == Some(checksum(&data)) {
+                    Incoming::Packet(data)
+                } else {
+                    Incoming::BadChecksum
+                }
+            }
+            // acks and anything between packets
+            _ => continue,
+        };
+        if incoming.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+// serves one gdb connection until it detaches, kills the machine or goes away. the
+// reader and writer are usually the two halves of a socket, see TcpStream::try_clone
+pub fn serve<R, W>(
+    reader: R,
+    mut writer: W,
+    chip8: &mut Chip8,
+    stub: &mut GdbStub,
+) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write,
+{
+    let (sender, incoming): (_, Receiver<Incoming>) = channel();
+    thread::spawn(move || read_packets(reader, sender));
+    let send = |writer: &mut W, data: &str| -> io::Result<()> {
+        writer.write_all(frame_packet(data).as_bytes())?;
+        writer.flush()
+    };
+    let send_stop = |writer: &mut W, stub: &mut GdbStub, stop: &str| -> io::Result<()> {
+        if let Some(output) = stub.take_error_output() {
+            send(writer, &output)?;
+        }
+        send(writer, stop)
+    };
+
+    while let Ok(event) = incoming.recv() {
+        let packet = match event {
+            Incoming::Packet(packet) => packet,
+            Incoming::BadChecksum => {
+                writer.write_all(b"-")?;
+                continue;
+            }
+            Incoming::Interrupt => continue,
+        };
+        writer.write_all(b"+")?;
+        match stub.handle(&packet, chip8) {
+            Action::Reply(reply) => send(&mut writer, &reply)?,
+            Action::Step => {
+                let stop = stub.step(chip8);
+                send_stop(&mut writer, stub, &stop)?
+            }
+            Action::Continue => {
+                let mut first = true;
+                let stop = loop {
+                    if let Some(stop) = stub.run(chip8, first) {
+                        break stop;
+                    }
+                    first = false;
+                    match incoming.try_recv() {
+                        // SIGINT
+                        Ok(Incoming::Interrupt) => break "S02".to_string(),
+                        Ok(_) | Err(TryRecvError::Empty) => {}
+                        Err(TryRecvError::Disconnected) => return Ok(()),
+                    }
+                };
+                send_stop(&mut writer, stub, &stop)?
+            }
+            Action::Close(reply) => {
+                if !reply.is_empty() {
+                    send(&mut writer, &reply)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::{TcpListener, TcpStream};
+
+    // 200: LD V0, 5, 202: ADD V0, 1, 204: JP 0x202
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::init();
+        chip8
+            .load_rom(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02])
+            .unwrap();
+        chip8
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut chip8 = chip8();
+        let mut stub = GdbStub::new(9);
+        chip8.I = 0x0123;
+        let registers = match stub.handle("g", &mut chip8) {
+            Action::Reply(reply) => reply,
+            action => panic!("{:?}", action),
+        };
+        assert_eq!(registers.len(), (16 + 2 + 2 + 3) * 2);
+        assert_eq!(&registers[32..40], "23010002");
+        assert_eq!(
+            stub.handle("P11=0602", &mut chip8),
+            Action::Reply("OK".to_string())
+        );
+        assert_eq!(chip8.pc, 0x206);
+        assert_eq!(
+            stub.handle("m200,4", &mut chip8),
+            Action::Reply("60057001".to_string())
+        );
+        assert_eq!(
+            stub.handle("M300,2:abcd", &mut chip8),
+            Action::Reply("OK".to_string())
+        );
+        assert_eq!(chip8.memory[0x300..0x302], [0xab, 0xcd]);
+        assert_eq!(
+            stub.handle("mffff,2", &mut chip8),
+            Action::Reply("E01".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_are_sent_to_gdb() {
+        let mut chip8 = Chip8::init();
+        // RET with nothing on the stack
+        chip8.load_rom(&[0x00, 0xee]).unwrap();
+        let mut stub = GdbStub::new(9);
+        assert_eq!(stub.step(&mut chip8), "S04");
+        let output = stub.take_error_output().unwrap();
+        assert_eq!(
+            output,
+            format!("O{}", hex_bytes(b"return with an empty stack at 0x200\n"))
+        );
+        assert!(stub.take_error_output().is_none());
+    }
+
+    #[test]
+    fn target_description_in_pieces() {
+        let mut chip8 = chip8();
+        let mut stub = GdbStub::new(9);
+        let mut description = String::new();
+        loop {
+            let packet = format!("qXfer:features:read:target.xml:{:x},100", description.len());
+            match stub.handle(&packet, &mut chip8) {
+                Action::Reply(reply) => {
+                    description += &reply[1..];
+                    if reply.starts_with('l') {
+                        break;
+                    }
+                }
+                action => panic!("{:?}", action),
+            }
+        }
+        assert_eq!(description, TARGET_XML);
+    }
+
+    // a scripted client on a real socket: set a breakpoint, continue to it, step
+    #[test]
+    fn serve_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut request = |data: &str| -> String {
+                writer.write_all(frame_packet(data).as_bytes()).unwrap();
+                let mut ack = [0u8];
+                reader.read_exact(&mut ack).unwrap();
+                assert_eq!(ack[0], b'+');
+                let mut reply = Vec::new();
+                reader.read_until(b'#', &mut reply).unwrap();
+                let mut sum = [0u8; 2];
+                reader.read_exact(&mut sum).unwrap();
+                writer.write_all(b"+").unwrap();
+                String::from_utf8(reply[1..reply.len() - 1].to_vec()).unwrap()
+            };
+            vec![
+                request("?"),
+                request("Z0,204,2"),
+                request("c"),
+                request("p11"),
+                request("p0"),
+                request("c"),
+                request("p0"),
+                request("z0,204,2"),
+                request("s"),
+                request("p11"),
+                request("D"),
+            ]
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut chip8 = chip8();
+        let mut stub = GdbStub::new(9);
+        serve(stream.try_clone().unwrap(), stream, &mut chip8, &mut stub).unwrap();
+        assert_eq!(
+            client.join().unwrap(),
+            vec!["S05", "OK", "S05", "0402", "06", "S05", "07", "OK", "S05", "0202", "OK"]
+        );
+    }
+}
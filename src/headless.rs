@@ -0,0 +1,382 @@
+// runs a rom without a window or sound card, for scripts and the build machines
+use chip8::display::{write_pbm, write_png};
+use chip8::gdb::{serve, GdbStub};
+use chip8::hash::to_hex;
+use chip8::input::ScriptedKeypad;
+use chip8::movie::Movie;
+use chip8::scheduler::{FrameBudget, Scheduler, Speed};
+use chip8::settings::Settings;
+use chip8::Chip8;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::process;
+
+const USAGE: &str = "usage: chip8-headless <rom> [options]
+
+  --frames N         frames to run at 60 per second, default 600
+  --until-halt       stop early once the rom halts (00FD) or jumps to itself
+  --speed N          instructions per frame, default 9
+  --ips N            instructions per second instead, spread over the frames
+  --vip-timing       run as many instructions as a COSMAC VIP managed each frame
+  --platform NAME    chip8, schip or xochip
+  --quirks NAME      vip, chip48, schip, xochip or modern
+  --foreground HEX   colour of lit pixels in the png, RRGGBB, default ffffff
+  --background HEX   colour of unlit pixels in the png, RRGGBB, default 000000
+  --seed N           seed for RND, random when left out
+  --keys FILE        key script, one `<frame> <down|up> <hex key>` per line
+  --movie FILE       play a movie back and fail if the final frame differs
+  --gdb ADDRESS      wait for gdb on host:port or unix:PATH and let it run the rom
+  --pbm FILE         write the final framebuffer as a pbm
+  --png FILE         write the final framebuffer as a png
+  --dump FILE        write the machine state as text, - for stdout
+  --state FILE       write a save state of the final machine
+  --help             show this
+
+roms the built in database knows get its platform, quirks, speed and colours,
+the options above override them";
+
+struct Options {
+    rom: String,
+    frames: u64,
+    until_halt: bool,
+    settings: Settings,
+    seed: Option<u64>,
+    keys: Option<String>,
+    movie: Option<String>,
+    gdb: Option<String>,
+    pbm: Option<String>,
+    png: Option<String>,
+    dump: Option<String>,
+    state: Option<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: String::new(),
+        frames: 600,
+        until_halt: false,
+        settings: Settings::default(),
+        seed: None,
+        keys: None,
+        movie: None,
+        gdb: None,
+        pbm: None,
+        png: None,
+        dump: None,
+        state: None,
+    };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        let bad_value = |value: &str| format!("bad value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--frames" => {
+                let frames = value()?;
+                options.frames = frames.parse().map_err(|_| bad_value(&frames))?;
+            }
+            "--until-halt" => options.until_halt = true,
+            "--seed" => {
+                let seed = value()?;
+                options.seed = Some(seed.parse().map_err(|_| bad_value(&seed))?);
+            }
+            "--keys" => options.keys = Some(value()?),
+            "--movie" => options.movie = Some(value()?),
+            "--gdb" => options.gdb = Some(value()?),
+            "--pbm" => options.pbm = Some(value()?),
+            "--png" => options.png = Some(value()?),
+            "--dump" => options.dump = Some(value()?),
+            "--state" => options.state = Some(value()?),
+            _ if options.settings.parse_flag(arg, &mut value)? => {}
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if rom.is_none() => rom = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    options.rom = rom.ok_or_else(|| "no rom given".to_string())?;
+    let inputs = [&options.keys, &options.movie, &options.gdb];
+    if inputs.iter().filter(|input| input.is_some()).count() > 1 {
+        return Err("only one of --keys, --movie and --gdb can be used".to_string());
+    }
+    if options.gdb.is_some() && options.settings.speed == Some(Speed::CosmacVip) {
+        return Err("--gdb continues a fixed number of instructions, not --vip-timing".to_string());
+    }
+    Ok(options)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(&options) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut chip8 = Chip8::init();
+    if let Some(seed) = options.seed {
+        chip8.set_random_seed(seed);
+    }
+    options
+        .settings
+        .load_game(&mut chip8, &options.rom)
+        .map_err(|error| format!("{}: {}", options.rom, error))?;
+    let speed = options.settings.speed_for(chip8.rom_info());
+
+    let frames = match (&options.movie, &options.gdb) {
+        (Some(path), _) => play_movie(&mut chip8, path)?,
+        (_, Some(address)) => {
+            debug_with_gdb(
+                &mut chip8,
+                address,
+                Scheduler::new(speed).instructions_per_frame().unwrap_or(9),
+            )
+            .map_err(|error| format!("gdb on {}: {}", address, error))?;
+            0
+        }
+        _ => run_frames(&mut chip8, options, speed)?,
+    };
+
+    let (width, height) = chip8.resolution();
+    if let Some(path) = &options.pbm {
+        write_file(path, |writer| {
+            write_pbm(writer, chip8.framebuffer(), width, height)
+        })?;
+    }
+    if let Some(path) = &options.png {
+        let palette = options.settings.palette_for(chip8.rom_info());
+        write_file(path, |writer| {
+            write_png(writer, chip8.framebuffer(), width, height, &palette)
+        })?;
+    }
+    if let Some(path) = &options.state {
+        fs::write(path, chip8.save_state())
+            .map_err(|error| format!("could not write {}: {}", path, error))?;
+    }
+    if let Some(path) = &options.dump {
+        let dump = machine_dump(&chip8, frames);
+        if path == "-" {
+            print!("{}", dump);
+        } else {
+            fs::write(path, dump)
+                .map_err(|error| format!("could not write {}: {}", path, error))?;
+        }
+    }
+    Ok(())
+}
+
+fn run_frames(chip8: &mut Chip8, options: &Options, speed: Speed) -> Result<u64, String> {
+    let mut keypad = match &options.keys {
+        Some(path) => {
+            let script = fs::read_to_string(path)
+                .map_err(|error| format!("could not read {}: {}", path, error))?;
+            ScriptedKeypad::parse(&script).map_err(|error| format!("{}: {}", path, error))?
+        }
+        None => ScriptedKeypad::new(Vec::new()),
+    };
+    // no clock to follow, only the share of instructions each frame gets
+    let mut scheduler = Scheduler::new(speed);
+    for frame in 0..options.frames {
+        if options.until_halt && (chip8.is_halted() || chip8.is_spinning()) {
+            return Ok(frame);
+        }
+        let ran = match scheduler.budget_for_tick() {
+            FrameBudget::Instructions(instructions) => chip8.run_frame(&mut keypad, instructions),
+            FrameBudget::Cycles(cycles) => chip8.run_frame_cycles(&mut keypad, cycles),
+        };
+        ran.map_err(|error| format!("frame {}: {}", frame, chip8.describe_error(&error)))?;
+    }
+    Ok(options.frames)
+}
+
+fn play_movie(chip8: &mut Chip8, path: &str) -> Result<u64, String> {
+    let text =
+        fs::read_to_string(path).map_err(|error| format!("could not read {}: {}", path, error))?;
+    let movie = Movie::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+    let hash = movie
+        .play(chip8)
+        .map_err(|error| format!("{}: {}", path, error))?;
+    if let Some(expected) = movie.final_frame {
+        if hash != expected {
+            return Err(format!(
+                "{}: final frame is {} but the movie expects {}",
+                path,
+                to_hex(&hash),
+                to_hex(&expected)
+            ));
+        }
+    }
+    Ok(movie.frames)
+}
+
+fn debug_with_gdb(chip8: &mut Chip8, address: &str, speed: u32) -> io::Result<()> {
+    let mut stub = GdbStub::new(speed);
+    if let Some(path) = address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            let _ = fs::remove_file(path);
+            let listener = UnixListener::bind(path)?;
+            eprintln!("waiting for gdb on {}", path);
+            let (stream, _) = listener.accept()?;
+            return serve(stream.try_clone()?, stream, chip8, &mut stub);
+        }
+        #[cfg(not(unix))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unix sockets are not available here, {}", path),
+        ));
+    }
+    let listener = TcpListener::bind(address)?;
+    eprintln!("waiting for gdb on {}", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    serve(stream.try_clone()?, stream, chip8, &mut stub)
+}
+
+fn write_file<F>(path: &str, write: F) -> Result<(), String>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let result = File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()
+    });
+    result.map_err(|error| format!("could not write {}: {}", path, error))
+}
+
+fn machine_dump(chip8: &Chip8, frames: u64) -> String {
+    let mut dump = String::new();
+    dump += &format!("rom         {}\n", to_hex(&chip8.rom_hash()));
+    dump += &format!("platform    {}\n", chip8.platform().name());
+    dump += &format!("frames      {}\n", frames);
+    dump += &format!("halted      {}\n", chip8.is_halted());
+    dump += &format!("pc          {:#05x}\n", chip8.pc());
+    dump += &format!("I           {:#05x}\n", chip8.index());
+    let registers: Vec<String> = chip8
+        .v_register
+        .iter()
+        .enumerate()
+        .map(|(index, value)| format!("v{:x}={:02x}", index, value))
+        .collect();
+    dump += &format!("registers   {}\n", registers.join(" "));
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|address| format!("{:#05x}", address))
+        .collect();
+    dump += &format!("stack       [{}]\n", stack.join(" "));
+    dump += &format!("delay timer {}\n", chip8.delay_timer());
+    dump += &format!("sound timer {}\n", chip8.sound_timer());
+    dump += &format!("seed        {}\n", chip8.random_seed());
+    let (width, height) = chip8.resolution();
+    dump += &format!("resolution  {}x{}\n", width, height);
+    dump += &format!("framebuffer {}\n", to_hex(&chip8.framebuffer_hash()));
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::hash::sha1;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        parse_options(&args)
+    }
+
+    #[test]
+    fn options_and_their_defaults() {
+        let options = parse("game.ch8 --frames 30 --until-halt --ips 600 --seed 7").unwrap();
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!(options.frames, 30);
+        assert!(options.until_halt);
+        assert_eq!(
+            options.settings.speed,
+            Some(Speed::InstructionsPerSecond(600))
+        );
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(parse("game.ch8").unwrap().frames, 600);
+    }
+
+    #[test]
+    fn bad_and_conflicting_options() {
+        let error = |args| parse(args).err().unwrap();
+        assert_eq!(error("--frames 30"), "no rom given");
+        assert_eq!(error("game.ch8 --frames"), "--frames needs a value");
+        assert_eq!(error("game.ch8 --frames x"), "bad value for --frames: x");
+        assert_eq!(error("game.ch8 --seed -1"), "bad value for --seed: -1");
+        assert_eq!(
+            error("game.ch8 --platform nes"),
+            "bad value for --platform: nes"
+        );
+        assert_eq!(error("game.ch8 --fast"), "unknown option --fast");
+        assert_eq!(error("game.ch8 other.ch8"), "unexpected argument other.ch8");
+        let only_one = "only one of --keys, --movie and --gdb can be used";
+        assert_eq!(error("game.ch8 --keys k --movie m"), only_one);
+        assert_eq!(error("game.ch8 --keys k --gdb :1234"), only_one);
+        assert_eq!(error("game.ch8 --movie m --gdb :1234"), only_one);
+        assert_eq!(
+            error("game.ch8 --gdb :1234 --vip-timing"),
+            "--gdb continues a fixed number of instructions, not --vip-timing"
+        );
+        assert!(parse("game.ch8 --gdb :1234 --speed 20").is_ok());
+    }
+
+    #[test]
+    fn dump_format() {
+        // LD V0, 0x2A then CALL 0x206, which clears the screen
+        let rom = [0x60, 0x2a, 0x22, 0x06, 0x12, 0x04, 0x00, 0xe0];
+        let mut chip8 = Chip8::init();
+        chip8.set_random_seed(7);
+        chip8.load_rom(&rom).unwrap();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        let dump = machine_dump(&chip8, 2);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], format!("rom         {}", to_hex(&sha1(&rom))));
+        assert_eq!(
+            lines[1..6],
+            [
+                "platform    chip8",
+                "frames      2",
+                "halted      false",
+                "pc          0x206",
+                "I           0x000",
+            ]
+        );
+        assert!(lines[6].starts_with("registers   v0=2a v1=00 "));
+        assert!(lines[6].ends_with(" vf=00"));
+        assert_eq!(
+            lines[7..12],
+            [
+                // the address of the CALL, RET steps over it
+                "stack       [0x202]",
+                "delay timer 0",
+                "sound timer 0",
+                "seed        7",
+                "resolution  64x32",
+            ]
+        );
+        assert!(lines[12].starts_with("framebuffer "));
+        assert_eq!(lines.len(), 13);
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::{BufReader, Read};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+// a change to one of the 16 hex keys. this is all the core ever sees of the host input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed(u8),
+    Released(u8),
+}
+
+pub trait KeypadInput {
+    // called once per frame and returns every key change since the last call
+    fn poll(&mut self) -> Vec<KeyEvent>;
+}
+
+pub fn qwerty_key(character: char) -> Option<u8> {
+    // maps the left side of a qwerty keyboard onto the hex keypad
+    //  1 2 3 4      1 2 3 C
+    //  Q W E R  ->  4 5 6 D
+    //  A S D F      7 8 9 E
+    //  Z X C V      A 0 B F
+    match character.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xc),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xd),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xe),
+        'z' => Some(0xa),
+        'x' => Some(0x0),
+        'c' => Some(0xb),
+        'v' => Some(0xf),
+        _ => None,
+    }
+}
+
+// terminals only report characters and never key releases, so every character
+// is treated as a tap: pressed on the frame it arrives and released on the next one
+pub struct TerminalKeypad {
+    characters: Receiver<u8>,
+    tapped: Vec<u8>,
+}
+
+impl TerminalKeypad {
+    pub fn new<R: Read + Send + 'static>(reader: R) -> TerminalKeypad {
+        // reading blocks so it is done on its own thread to keep poll from stalling a frame
+        let (sender, characters) = channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(reader).bytes() {
+                match byte {
+                    Ok(byte) => {
+                        if sender.send(byte).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+        TerminalKeypad {
+            characters,
+            tapped: Vec::new(),
+        }
+    }
+
+    pub fn stdin() -> TerminalKeypad {
+        TerminalKeypad::new(std::io::stdin())
+    }
+}
+
+impl KeypadInput for TerminalKeypad {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        let mut events: Vec<KeyEvent> = self.tapped.drain(..).map(KeyEvent::Released).collect();
+        for byte in self.characters.try_iter() {
+            if let Some(key) = qwerty_key(byte as char) {
+                events.push(KeyEvent::Pressed(key));
+                self.tapped.push(key);
+            }
+        }
+        events
+    }
+}
+
+pub(crate) fn parse_key_events(
+    script: &str,
+    first_line_number: usize,
+) -> Result<Vec<(u64, KeyEvent)>, String> {
+    // one change per line in the form `<frame> <down|up> <hex key>`, # starts a comment
+    let mut events = Vec::new();
+    for (line_number, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad_line = || {
+            format!(
+                "line {}: expected `<frame> <down|up> <key>`",
+                line_number + first_line_number
+            )
+        };
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(bad_line());
+        }
+        let frame: u64 = parts[0].parse().map_err(|_| bad_line())?;
+        let key = u8::from_str_radix(parts[2].trim_start_matches("0x"), 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .ok_or_else(bad_line)?;
+        let event = match parts[1] {
+            "down" => KeyEvent::Pressed(key),
+            "up" => KeyEvent::Released(key),
+            _ => return Err(bad_line()),
+        };
+        events.push((frame, event));
+    }
+    Ok(events)
+}
+
+pub(crate) fn format_key_event(frame: u64, event: KeyEvent) -> String {
+    match event {
+        KeyEvent::Pressed(key) => format!("{} down {:x}", frame, key),
+        KeyEvent::Released(key) => format!("{} up {:x}", frame, key),
+    }
+}
+
+// plays back a fixed list of key changes, each tagged with the frame it happens on
+pub struct ScriptedKeypad {
+    events: Vec<(u64, KeyEvent)>,
+    next_event: usize,
+    frame: u64,
+}
+
+impl ScriptedKeypad {
+    pub fn new(mut events: Vec<(u64, KeyEvent)>) -> ScriptedKeypad {
+        events.sort_by_key(|event| event.0);
+        ScriptedKeypad {
+            events,
+            next_event: 0,
+            frame: 0,
+        }
+    }
+
+    pub fn parse(script: &str) -> Result<ScriptedKeypad, String> {
+        Ok(ScriptedKeypad::new(parse_key_events(script, 1)?))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_event >= self.events.len()
+    }
+}
+
+impl KeypadInput for ScriptedKeypad {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        while self.next_event < self.events.len() && self.events[self.next_event].0 <= self.frame {
+            events.push(self.events[self.next_event].1);
+            self.next_event += 1;
+        }
+        self.frame += 1;
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qwerty_layout() {
+        assert_eq!(qwerty_key('1'), Some(0x1));
+        assert_eq!(qwerty_key('V'), Some(0xf));
+        assert_eq!(qwerty_key('x'), Some(0x0));
+        assert_eq!(qwerty_key('p'), None);
+    }
+
+    #[test]
+    fn scripted_events_by_frame() {
+        let mut keypad = ScriptedKeypad::parse("# test\n0 down 5\n2 up 5\n2 down a\n").unwrap();
+        assert_eq!(keypad.poll(), vec![KeyEvent::Pressed(5)]);
+        assert_eq!(keypad.poll(), vec![]);
+        assert_eq!(
+            keypad.poll(),
+            vec![KeyEvent::Released(5), KeyEvent::Pressed(0xa)]
+        );
+        assert!(keypad.is_finished());
+    }
+
+    #[test]
+    fn scripted_bad_line() {
+        assert!(ScriptedKeypad::parse("0 down 5\nfive up 5").is_err());
+        assert!(ScriptedKeypad::parse("0 down 10").is_err());
+    }
+}
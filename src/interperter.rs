@@ -1,8 +1,21 @@
+use crate::audio::{AudioBackend, AudioPattern};
+use crate::database::{RomDatabase, RomInfo};
+use crate::display::DisplayBackend;
+use crate::error::Chip8Error;
+use crate::hash::sha1;
+use crate::input::{KeyEvent, KeypadInput};
+use crate::octo::{compile, SourceMap};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::random::{Random, RandomAlgorithm};
+use crate::timing::{vip_cycles, SKIP_CYCLES};
 use rand::{thread_rng, Rng};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
+use std::path::Path;
+
+// how many calls can be nested before CALL fails
+pub(crate) const STACK_DEPTH: usize = 16;
 
 #[allow(unused)]
 #[derive(Debug, PartialEq)]
@@ -43,39 +56,111 @@ pub enum Opcode {
     LD_BV,
     LD_IV,
     LD_VI,
+    // super chip 1.1
+    SCD,
+    SCR,
+    SCL,
+    EXIT,
+    LOW,
+    HIGH,
+    LD_HFV,
+    LD_RV,
+    LD_VR,
+    // xo chip
+    SCU,
+    LD_IVV,
+    LD_VVI,
+    LD_IL,
+    PLANE,
+    AUDIO,
+    PITCH,
 }
 
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+// super chip high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// super chip 8x10 digits 0-9, loaded after the small font
+const BIG_FONT_ADDRESS: usize = 0x0a0;
+const BIG_FONT: [u8; 100] = [
+    0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c, // 1
+    0x3e, 0x7f, 0xc3, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xff, 0xff, // 2
+    0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c, // 3
+    0x06, 0x0e, 0x1e, 0x36, 0x66, 0xc6, 0xff, 0xff, 0x06, 0x06, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfe, 0x03, 0xc3, 0x7e, 0x3c, // 5
+    0x3e, 0x7c, 0xe0, 0xc0, 0xfc, 0xfe, 0xc3, 0xc3, 0x7e, 0x3c, // 6
+    0xff, 0xff, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7e, 0x7e, 0xc3, 0xc3, 0x7e, 0x3c, // 8
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7f, 0x3f, 0x03, 0x03, 0x3e, 0x7c, // 9
+];
+
 #[derive(Debug)]
+#[allow(non_snake_case)]
 pub struct Chip8 {
     // 0x000-0x1ff chip 8 interperter
     // 0x050-0x0a0 built in pixel font set
+    // 0x0a0-0x104 super chip big font set
     // 0x200-0xfff program ROM and work RAM
-    memory: [u8; 4096],
+    // 0x1000-0xffff more program ROM and work RAM on xo chip only
+    pub(crate) memory: Vec<u8>,
+    pub(crate) platform: Platform,
+    // sha-1 of the loaded rom, all zero until one is loaded
+    pub(crate) rom_hash: [u8; 20],
+    // set when the rom was compiled from octo source, to point errors at its lines
+    pub(crate) source_map: Option<SourceMap>,
+    // what the rom database knows about the rom load_game loaded
+    pub(crate) rom_info: Option<RomInfo>,
 
     //gerneral purpose registers
     pub v_register: [u8; 16],
 
     //index register
-    #[allow(non_snake_case)]
-    I: u16,
+    pub(crate) I: u16,
 
     //program counter
-    pc: u16,
-
-    //graphics
-    pub gfx: [u8; 64 * 32],
+    pub(crate) pc: u16,
+
+    //graphics, row by row at the active resolution so only the first
+    //64 * 32 pixels are used until high resolution mode is turned on.
+    //each pixel holds one bit per xo chip bitplane, so 0-3 indexes a palette
+    pub gfx: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+    pub(crate) hires: bool,
+    // bitplanes that DRW, CLS and the scrolls work on, only plane 1 unless changed by PLANE
+    pub(crate) planes: u8,
+    // set whenever gfx changes so the display only gets new frames
+    pub(crate) draw_flag: bool,
 
     //timers that count down to zero once per second when
     //greater then zero
-    delay_timer: u8,
-    sound_timer: u8,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
 
-    //stack to store the stack pointer before a jump
-    stack: [u16; 16],
-    sp: u16,
+    //stack to store the stack pointer before a jump. sp counts the calls and
+    //stack[sp] holds the latest return address, slot 0 is never used
+    pub(crate) stack: [u16; STACK_DEPTH + 1],
+    pub(crate) sp: u16,
 
     //hex based keypad 0 - f
     pub key: [bool; 16],
+
+    // xo chip audio pattern loaded by AUDIO and its playback pitch
+    pub(crate) audio_pattern: Option<[u8; 16]>,
+    pub(crate) pitch: u8,
+
+    // super chip rpl user flags saved by LD_RV and restored by LD_VR
+    pub(crate) rpl_flags: [u8; 16],
+    // set by EXIT, step does nothing once the program has quit
+    pub(crate) halted: bool,
+    pub(crate) random: Random,
+    // machine cycles left in the current frame on VIP timing, negative when the last
+    // instruction ran over. every step spends from it whether or not it is used
+    pub(crate) cycle_budget: i64,
+
+    // which interpretation of the ambiguous instructions to use
+    pub quirks: Quirks,
 }
 
 const INIT_MEMORY: [u8; 4096] = [
@@ -215,8 +300,14 @@ const INIT_MEMORY: [u8; 4096] = [
 
 impl Chip8 {
     pub fn init() -> Chip8 {
+        let mut memory = INIT_MEMORY;
+        memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
         Chip8 {
-            memory: INIT_MEMORY,
+            memory: memory.to_vec(),
+            platform: Platform::default(),
+            rom_hash: [0; 20],
+            source_map: None,
+            rom_info: None,
             v_register: [0; 16],
             //v0  : 0,
             //v1  : 0,
@@ -236,229 +327,381 @@ impl Chip8 {
             //v15 : 0,
             I: 0,
             pc: 0x200,
-            stack: [0; 16],
+            stack: [0; STACK_DEPTH + 1],
             sp: 0,
-            gfx: [0u8; 64 * 32],
+            gfx: [0u8; HIRES_WIDTH * HIRES_HEIGHT],
+            hires: false,
+            planes: 1,
+            draw_flag: true,
             delay_timer: 0u8,
             sound_timer: 0u8,
             key: [false; 16],
+            audio_pattern: None,
+            pitch: 64,
+            rpl_flags: [0; 16],
+            halted: false,
+            // a fresh seed every run, set_random_seed makes a run repeatable
+            random: Random::new(RandomAlgorithm::Xorshift, thread_rng().gen()),
+            cycle_budget: 0,
+            quirks: Quirks::default(),
         }
     }
 
-    pub fn load_game(&mut self, path: String) {
-        let mut file = File::open(path).unwrap();
-        let mut buf = [0u8; 0xfff - 0x200];
-        file.read(&mut buf).unwrap();
-        let mut i = 0x200;
-        for byte in &buf {
-            self.memory[i] = *byte;
-            i += 1;
-        }
+    pub fn platform(&self) -> Platform {
+        self.platform
     }
 
-    pub fn check_key_state(&mut self, user_event: sdl2::event::Event) {
-        // this will handle key input. maybe add an exit to it that might need to be somewhere else though
-        match user_event {
-            // can be refactored useing whats in the match above this in the main code
-            Event::KeyDown {
-                keycode: Some(Keycode::Num1),
-                ..
-            } => self.key[0x1] = true, //1
+    pub fn rom_hash(&self) -> [u8; 20] {
+        self.rom_hash
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::Num1),
-                ..
-            } => self.key[0x1] = false, //1
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::Num2),
-                ..
-            } => self.key[0x2] = true, //2
+    pub fn random_seed(&self) -> u64 {
+        self.random.seed()
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::Num2),
-                ..
-            } => self.key[0x2] = false, //2
+    pub fn random_algorithm(&self) -> RandomAlgorithm {
+        self.random.algorithm()
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::Num3),
-                ..
-            } => self.key[0x3] = true, //3
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.random.reseed(seed);
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::Num3),
-                ..
-            } => self.key[0x3] = false, //3
+    pub fn set_random(&mut self, algorithm: RandomAlgorithm, seed: u64) {
+        self.random = Random::new(algorithm, seed);
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::Q),
-                ..
-            } => self.key[0x4] = true, //4
+    pub fn set_platform(&mut self, platform: Platform) {
+        // also resets the quirks to what the platform expects, change them afterwards to override
+        self.platform = platform;
+        self.memory.resize(platform.memory_size(), 0);
+        self.quirks = platform.default_quirks();
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::Q),
-                ..
-            } => self.key[0x4] = false, //4
+    // .8o files are octo source and get compiled first. roms found in the bundled
+    // database get the platform and quirks it lists
+    pub fn load_game<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Chip8Error> {
+        self.load_game_on(path, None)
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::W),
-                ..
-            } => self.key[0x5] = true, //5
+    // load_game on platform instead of the one the database lists, set before the rom
+    // is loaded so a big xo chip rom fits. change the quirks afterwards to override them
+    pub fn load_game_on<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        platform: Option<Platform>,
+    ) -> Result<(), Chip8Error> {
+        if let Some(platform) = platform {
+            self.set_platform(platform);
+        }
+        let path = path.as_ref();
+        let octo = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("8o"));
+        if octo {
+            let source = fs::read_to_string(path)?;
+            let program =
+                compile(&source, &path.display().to_string()).map_err(Chip8Error::Compile)?;
+            self.load_rom(&program.rom)?;
+            self.source_map = Some(program.source_map);
+            return Ok(());
+        }
+        let mut file = File::open(path)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        // before loading, xo chip roms need the bigger memory to fit
+        let info = RomDatabase::bundled().lookup(&sha1(&buf));
+        if let (Some(info), None) = (&info, platform) {
+            self.apply_rom_info(info);
+        }
+        self.load_rom(&buf)?;
+        self.rom_info = info;
+        Ok(())
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::W),
-                ..
-            } => self.key[0x5] = false, //5
+    // switches to the platform and quirks the database lists for a rom
+    pub fn apply_rom_info(&mut self, info: &RomInfo) {
+        if let Some(platform) = info.platform {
+            self.set_platform(platform);
+        }
+        if let Some(quirks) = info.quirks {
+            self.quirks = quirks;
+        }
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::E),
-                ..
-            } => self.key[0x6] = true, //6
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.memory.len() - 0x200;
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+        self.memory[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        self.rom_hash = sha1(rom);
+        self.source_map = None;
+        self.rom_info = None;
+        Ok(())
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::E),
-                ..
-            } => self.key[0x6] = false, //6
+    // the octo source line that emitted the code at address
+    pub fn source_line(&self, address: u16) -> Option<usize> {
+        self.source_map.as_ref()?.line(address)
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::A),
-                ..
-            } => self.key[0x7] = true, //7
+    // the error's message, followed by the source line it came from when known
+    pub fn describe_error(&self, error: &Chip8Error) -> String {
+        match error.pc().and_then(|pc| self.source_line(pc)) {
+            Some(line) => format!("{} (line {})", error, line),
+            None => error.to_string(),
+        }
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::A),
-                ..
-            } => self.key[0x7] = false, //7
+    pub fn set_key(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent::Pressed(key) => self.key[(key & 0x0f) as usize] = true,
+            KeyEvent::Released(key) => self.key[(key & 0x0f) as usize] = false,
+        }
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::S),
-                ..
-            } => self.key[0x8] = true, //8
+    pub fn update_keys(&mut self, input: &mut dyn KeypadInput) {
+        for event in input.poll() {
+            self.set_key(event);
+        }
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::S),
-                ..
-            } => self.key[0x8] = false, //8
+    pub fn update_display(&mut self, display: &mut dyn DisplayBackend) {
+        if self.draw_flag {
+            let (width, height) = self.resolution();
+            display.draw(&self.gfx[..width * height], width, height);
+            self.draw_flag = false;
+        }
+        display.present();
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::D),
-                ..
-            } => self.key[0x9] = true, //9
+    pub fn update_audio(&mut self, audio: &mut dyn AudioBackend) {
+        if let Some(bits) = self.audio_pattern {
+            audio.set_pattern(AudioPattern {
+                bits,
+                pitch: self.pitch,
+            });
+        }
+        audio.set_playing(self.sound_timer > 0);
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::D),
-                ..
-            } => self.key[0x9] = false, //9
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::Z),
-                ..
-            } => self.key[0xa] = true, //A
+    // the visible part of gfx at the current resolution
+    pub fn framebuffer(&self) -> &[u8] {
+        let (width, height) = self.resolution();
+        &self.gfx[..width * height]
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::Z),
-                ..
-            } => self.key[0xa] = false, //A
+    pub fn framebuffer_hash(&self) -> [u8; 20] {
+        sha1(self.framebuffer())
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::X),
-                ..
-            } => self.key[0x0] = true, //0
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::X),
-                ..
-            } => self.key[0x0] = false, //0
+    // true when the next instruction is a jump to itself, the usual way for a rom or
+    // test suite to stop once it has nothing left to do
+    pub fn is_spinning(&self) -> bool {
+        match self.memory.get(self.pc as usize..self.pc as usize + 2) {
+            Some(opcode) => {
+                opcode[0] & 0xf0 == 0x10
+                    && u16::from_be_bytes([opcode[0], opcode[1]]) & 0x0fff == self.pc
+            }
+            None => false,
+        }
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::C),
-                ..
-            } => self.key[0xb] = true, //B
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::C),
-                ..
-            } => self.key[0xb] = false, //B
+    pub fn index(&self) -> u16 {
+        self.I
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::Num4),
-                ..
-            } => self.key[0xc] = true, //C
+    // the addresses of the CALLs currently on the stack, oldest first. RET goes back
+    // to the instruction after each one. CALL bumps sp before storing, so slot 0 is
+    // never used
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[1..=self.sp as usize]
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::Num4),
-                ..
-            } => self.key[0xc] = false, //C
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::R),
-                ..
-            } => self.key[0xd] = true, //D
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::R),
-                ..
-            } => self.key[0xd] = false, //D
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::F),
-                ..
-            } => self.key[0xe] = true, //E
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::F),
-                ..
-            } => self.key[0xe] = false, //E
+    // one 60th of a second without any frontend: keys are read once at the start,
+    // then the instructions run and the timers tick
+    pub fn run_frame(
+        &mut self,
+        input: &mut dyn KeypadInput,
+        instructions: u32,
+    ) -> Result<(), Chip8Error> {
+        self.update_keys(input);
+        for _ in 0..instructions {
+            self.step()?;
+        }
+        self.decrease_timers();
+        Ok(())
+    }
+
+    // the same as run_frame but spends a budget of VIP machine cycles instead of a
+    // count of instructions, see timing::VIP_CYCLES_PER_FRAME
+    pub fn run_frame_cycles(
+        &mut self,
+        input: &mut dyn KeypadInput,
+        cycles: u32,
+    ) -> Result<(), Chip8Error> {
+        self.update_keys(input);
+        self.add_cycles(cycles);
+        while self.has_cycles_left() && !self.halted {
+            self.step()?;
+        }
+        self.decrease_timers();
+        Ok(())
+    }
 
-            Event::KeyDown {
-                keycode: Some(Keycode::V),
-                ..
-            } => self.key[0xf] = true, //F
+    // gives the next frame its cycles. an instruction that ran past the end of the
+    // last frame is paid back, anything left unspent (halted, paused) is dropped
+    pub fn add_cycles(&mut self, cycles: u32) {
+        let cycles = cycles as i64;
+        self.cycle_budget = self.cycle_budget.clamp(-cycles, 0) + cycles;
+    }
 
-            Event::KeyUp {
-                keycode: Some(Keycode::V),
-                ..
-            } => self.key[0xf] = false, //F
+    pub fn has_cycles_left(&self) -> bool {
+        self.cycle_budget > 0
+    }
 
-            _ => (),
+    // runs one instruction and returns the machine cycles it took on the COSMAC VIP
+    pub fn step(&mut self) -> Result<u32, Chip8Error> {
+        if self.halted {
+            return Ok(0);
+        }
+        self.check_memory_range(self.pc as usize, 2)?;
+        let opcode = self.fetch_opcode();
+        match decode_opcode(opcode) {
+            Some(decoded_opcode) => {
+                let draws = decoded_opcode.0 == Opcode::DRW;
+                let cycles = self.execute_opcode(decoded_opcode)?;
+                self.cycle_budget -= cycles as i64;
+                // the VIP waits for the display interrupt to draw, so on VIP timing a
+                // sprite uses up whatever is left of the frame
+                if draws {
+                    self.cycle_budget = self.cycle_budget.min(0);
+                }
+                Ok(cycles)
+            }
+            None => Err(Chip8Error::UnknownOpcode {
+                pc: self.pc,
+                opcode,
+            }),
         }
     }
 
+    fn check_memory_range(&self, address: usize, length: usize) -> Result<(), Chip8Error> {
+        if address + length > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfRange {
+                pc: self.pc,
+                address: address + length - 1,
+            });
+        }
+        Ok(())
+    }
+
     pub fn fetch_opcode(&self) -> [u8; 2] {
         let mut opcode = [0u8; 2];
-        opcode[0] = self.memory[self.pc as usize];
-        opcode[1] = self.memory[(self.pc + 1) as usize];
+        // past the end of memory reads as 0, step reports it before executing
+        let byte = |address: usize| self.memory.get(address).copied().unwrap_or(0);
+        opcode[0] = byte(self.pc as usize);
+        opcode[1] = byte(self.pc as usize + 1);
         //maybe increment pc now
         opcode
     }
-    pub fn execute_opcode(&mut self, opcode: (Opcode, [u8; 2])) {
+    // returns the machine cycles the instruction took on the COSMAC VIP, see timing.rs
+    pub fn execute_opcode(&mut self, opcode: (Opcode, [u8; 2])) -> Result<u32, Chip8Error> {
+        let pc = self.pc;
+        let cycles = vip_cycles(self, &opcode);
+        let skips = matches!(
+            opcode.0,
+            Opcode::SE_VB
+                | Opcode::SNE_VB
+                | Opcode::SE_VV
+                | Opcode::SNE_VV
+                | Opcode::SKP
+                | Opcode::SKNP
+        );
+        self.execute(opcode)?;
+        if skips && self.pc != pc.wrapping_add(2) {
+            return Ok(cycles + SKIP_CYCLES);
+        }
+        Ok(cycles)
+    }
+
+    fn execute(&mut self, opcode: (Opcode, [u8; 2])) -> Result<(), Chip8Error> {
         //put this here since it will modify the chip8struct
         // not sure if I should use to option here or handle it in main
         match opcode.0 {
             Opcode::CLS => {
-                self.gfx = [0; 64 * 32];
-                self.pc += 2;
+                for pixel in self.gfx.iter_mut() {
+                    *pixel &= !self.planes;
+                }
+                self.draw_flag = true;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::RET => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow { pc: self.pc });
+                }
                 self.pc = self.stack[self.sp as usize];
                 self.sp -= 1;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
-            Opcode::SYS => self.pc += 2, //apparently this is ignored these days ,
+            Opcode::SYS => self.pc = self.pc.wrapping_add(2), //apparently this is ignored these days ,
             Opcode::JP_A => {
                 let mut addr: u16 = opcode.1[0] as u16;
                 addr -= 0x10;
-                addr = addr << 8;
+                addr <<= 8;
                 addr += opcode.1[1] as u16;
                 self.pc = addr;
             }
             Opcode::CALL => {
+                if self.sp as usize >= STACK_DEPTH {
+                    return Err(Chip8Error::StackOverflow { pc: self.pc });
+                }
                 self.sp += 1;
                 self.stack[self.sp as usize] = self.pc;
                 let mut addr: u16 = opcode.1[0] as u16;
                 addr -= 0x20;
 
-                addr = addr << 8;
+                addr <<= 8;
 
                 addr += opcode.1[1] as u16;
 
@@ -466,60 +709,66 @@ impl Chip8 {
             }
             Opcode::SE_VB => {
                 if self.v_register[(opcode.1[0] - 0x30) as usize] == opcode.1[1] {
-                    self.pc += 4;
+                    self.skip_next_instruction();
                 } else {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Opcode::SNE_VB => {
                 if self.v_register[(opcode.1[0] - 0x40) as usize] != opcode.1[1] {
-                    self.pc += 4;
+                    self.skip_next_instruction();
                 } else {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Opcode::SE_VV => {
                 if self.v_register[(opcode.1[0] - 0x50) as usize]
                     == self.v_register[(opcode.1[1] >> 4) as usize]
                 {
-                    self.pc += 4;
+                    self.skip_next_instruction();
                 } else {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Opcode::LD_VB => {
                 self.v_register[(opcode.1[0] - 0x60) as usize] = opcode.1[1];
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::ADD_VB => {
                 self.v_register[(opcode.1[0] - 0x70) as usize] = self.v_register
                     [(opcode.1[0] - 0x70) as usize]
                     .overflowing_add(opcode.1[1])
                     .0;
-                self.pc += 2
+                self.pc = self.pc.wrapping_add(2)
             }
             Opcode::LD_VV => {
                 self.v_register[(opcode.1[0] - 0x80) as usize] =
                     self.v_register[(opcode.1[1] >> 4) as usize];
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::OR => {
-                self.v_register[(opcode.1[0] - 0x80) as usize] = self.v_register
-                    [(opcode.1[0] - 0x80) as usize]
-                    | self.v_register[(opcode.1[1] >> 4) as usize];
-                self.pc += 2;
+                self.v_register[(opcode.1[0] - 0x80) as usize] |=
+                    self.v_register[(opcode.1[1] >> 4) as usize];
+                if self.quirks.logic_resets_vf {
+                    self.v_register[15] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::AND => {
-                self.v_register[(opcode.1[0] - 0x80) as usize] = self.v_register
-                    [(opcode.1[0] - 0x80) as usize]
-                    & self.v_register[(opcode.1[1] >> 4) as usize];
-                self.pc += 2;
+                self.v_register[(opcode.1[0] - 0x80) as usize] &=
+                    self.v_register[(opcode.1[1] >> 4) as usize];
+                if self.quirks.logic_resets_vf {
+                    self.v_register[15] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::XOR => {
-                self.v_register[(opcode.1[0] - 0x80) as usize] = self.v_register
-                    [(opcode.1[0] - 0x80) as usize]
-                    ^ self.v_register[(opcode.1[1] >> 4) as usize];
-                self.pc += 2;
+                self.v_register[(opcode.1[0] - 0x80) as usize] ^=
+                    self.v_register[(opcode.1[1] >> 4) as usize];
+                if self.quirks.logic_resets_vf {
+                    self.v_register[15] = 0;
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::ADD_VV => {
                 let overflow_add = self.v_register[(opcode.1[0] - 0x80) as usize]
@@ -530,7 +779,7 @@ impl Chip8 {
                 } else {
                     self.v_register[15] = 0;
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::SUB => {
                 let overflow_sub = self.v_register[(opcode.1[0] - 0x80) as usize]
@@ -541,17 +790,14 @@ impl Chip8 {
                 } else {
                     self.v_register[15] = 1;
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::SHR => {
-                if self.v_register[(opcode.1[0] - 0x80) as usize].trailing_zeros() < 1 {
-                    self.v_register[15] = 1;
-                } else {
-                    self.v_register[15] = 0
-                };
-                self.v_register[(opcode.1[0] - 0x80) as usize] =
-                    self.v_register[(opcode.1[0] - 0x80) as usize] >> 1;
-                self.pc += 2;
+                let source = self.shift_source(opcode.1);
+                self.v_register[(opcode.1[0] - 0x80) as usize] = source >> 1;
+                // flag goes last so it wins when VX is VF
+                self.v_register[15] = source & 0x01;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::SUBN => {
                 let overflow_sub = self.v_register[(opcode.1[1] >> 4) as usize]
@@ -562,150 +808,337 @@ impl Chip8 {
                 } else {
                     self.v_register[15] = 1;
                 }
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::SHL => {
-                if self.v_register[(opcode.1[0] - 0x80) as usize] > 0x80 {
-                    self.v_register[15] = 1;
-                } else {
-                    self.v_register[15] = 0
-                };
-                self.v_register[(opcode.1[0] - 0x80) as usize] =
-                    self.v_register[(opcode.1[0] - 0x80) as usize] << 1;
-                self.pc += 2;
+                let source = self.shift_source(opcode.1);
+                self.v_register[(opcode.1[0] - 0x80) as usize] = source << 1;
+                self.v_register[15] = source >> 7;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::SNE_VV => {
                 if self.v_register[(opcode.1[0] - 0x90) as usize]
                     != self.v_register[(opcode.1[1] >> 4) as usize]
                 {
-                    self.pc += 4;
+                    self.skip_next_instruction();
                 } else {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Opcode::LD_IA => {
-                self.I = (opcode.1[0] as u16 - 0xa0 << 8) + opcode.1[1] as u16;
-                self.pc += 2;
+                self.I = ((opcode.1[0] as u16 - 0xa0) << 8) + opcode.1[1] as u16;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::JP_VA => {
+                let offset_register = if self.quirks.jump_uses_vx {
+                    (opcode.1[0] - 0xb0) as usize
+                } else {
+                    0
+                };
                 self.pc = (((opcode.1[0] as u16 - 0xb0) << 8) + opcode.1[1] as u16)
-                    + self.v_register[0] as u16;
+                    + self.v_register[offset_register] as u16;
             }
             Opcode::RND => {
-                let mut rng = thread_rng();
-                let random_num: u8 = rng.gen();
+                let random_num = self.random.next_byte();
                 let combo = opcode.1[1] & random_num;
                 self.v_register[(opcode.1[0] - 0xc0) as usize] = combo;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::DRW => {
-                // read value at I into a buffer one byte at a time and increment I. once xor newvalue with taht put the result into location of I and decrement it
-                //thisresults in I being the same as before and not needing a diferent value to track it
-                for row in 0..(opcode.1[1] % 16) {
-                    //this gets just the lowest byte
-                    let new_sprite: u8 = self.memory[(self.I + row as u16) as usize];
-                    let mut current_sprite: u8 = 0;
-                    for index in 0..8 {
-                        current_sprite = current_sprite << 1;
-                        current_sprite += self.gfx[xy_coord(
-                            (self.v_register[(opcode.1[0] - 0xd0) as usize] + index) as u32,
-                            (self.v_register[(opcode.1[1] >> 4) as usize] + row) as u32,
-                        ) as usize];
-                    }
-
-                    let mut new_bytes = current_sprite ^ new_sprite;
-                    self.v_register[0x0f as usize] = 0;
-                    for index in (0..8).rev() {
-                        if self.gfx[xy_coord(
-                            (self.v_register[(opcode.1[0] - 0xd0) as usize] + index) as u32,
-                            (self.v_register[(opcode.1[1] >> 4) as usize] + row) as u32,
-                        ) as usize]
-                            == 1
-                            && new_bytes % 2 == 0
-                        {
-                            self.v_register[0x0f as usize] = 1;
+                // DXY0 draws a 16x16 sprite made of two bytes per row
+                let (sprite_width, height) = match opcode.1[1] % 16 {
+                    0 => (16, 16),
+                    rows => (8, rows as u32),
+                };
+                let bytes_per_row = sprite_width / 8;
+                let sprite_size = (height * bytes_per_row) as usize;
+                // with both planes selected the sprite for plane 2 follows the one for plane 1
+                let planes: Vec<u8> = [1u8, 2]
+                    .iter()
+                    .copied()
+                    .filter(|plane| self.planes & plane != 0)
+                    .collect();
+                self.check_memory_range(self.I as usize, sprite_size * planes.len())?;
+                let (width, screen_height) = self.resolution();
+                // the starting position always wraps, only the sprite itself can be clipped
+                let x = self.v_register[(opcode.1[0] - 0xd0) as usize] as u32 % width as u32;
+                let y = self.v_register[(opcode.1[1] >> 4) as usize] as u32 % screen_height as u32;
+                self.v_register[0x0f] = 0;
+                for (plane_index, plane) in planes.iter().enumerate() {
+                    let sprite_address = self.I as usize + plane_index * sprite_size;
+                    for row in 0..height {
+                        let address = sprite_address + (row * bytes_per_row) as usize;
+                        let sprite = if sprite_width == 16 {
+                            (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16
+                        } else {
+                            (self.memory[address] as u16) << 8
+                        };
+                        for column in 0..sprite_width {
+                            if sprite & (0x8000 >> column) == 0 {
+                                continue;
+                            }
+                            let (pixel_x, pixel_y) = (x + column, y + row);
+                            if self.quirks.clip_sprites
+                                && (pixel_x >= width as u32 || pixel_y >= screen_height as u32)
+                            {
+                                continue;
+                            }
+                            let index = self.xy_coord(pixel_x, pixel_y);
+                            if self.gfx[index] & plane != 0 {
+                                self.v_register[0x0f] = 1;
+                            }
+                            self.gfx[index] ^= plane;
                         }
-                        self.gfx[xy_coord(
-                            (self.v_register[(opcode.1[0] - 0xd0) as usize] + index) as u32,
-                            (self.v_register[(opcode.1[1] >> 4) as usize] + row) as u32,
-                        ) as usize] = new_bytes % 2;
-                        new_bytes /= 2;
                     }
                 }
-                self.pc += 2;
+                self.draw_flag = true;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::SKP => {
-                if self.key[self.v_register[(opcode.1[0] - 0xe0) as usize] as usize] {
-                    self.pc += 4;
+                // only the low nibble of VX picks a key, like the VIP
+                let key = (self.v_register[(opcode.1[0] - 0xe0) as usize] & 0x0f) as usize;
+                if self.key[key] {
+                    self.skip_next_instruction();
                 } else {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Opcode::SKNP => {
-                if !self.key[self.v_register[(opcode.1[0] - 0xe0) as usize] as usize] {
-                    self.pc += 4;
+                // only the low nibble of VX picks a key, like the VIP
+                let key = (self.v_register[(opcode.1[0] - 0xe0) as usize] & 0x0f) as usize;
+                if !self.key[key] {
+                    self.skip_next_instruction();
                 } else {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Opcode::LD_VDT => {
                 self.v_register[(opcode.1[0] - 0xf0) as usize] = self.delay_timer;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::LD_VK => {
                 let mut pause = true;
 
                 for button in 0..16 {
-                    if self.key[button] == true {
+                    if self.key[button] {
                         pause = false;
                         self.v_register[(opcode.1[0] - 0xf0) as usize] = button as u8;
                     }
                 }
 
                 if !pause {
-                    self.pc += 2;
+                    self.pc = self.pc.wrapping_add(2);
                 }
             }
             Opcode::LD_DTV => {
-                self.v_register[(opcode.1[0] - 0xf0) as usize] = self.delay_timer;
-                self.pc += 2;
+                self.delay_timer = self.v_register[(opcode.1[0] - 0xf0) as usize];
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::LD_STV => {
                 self.sound_timer = self.v_register[(opcode.1[0] - 0xf0) as usize];
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::ADD_IV => {
-                self.I += self.v_register[(opcode.1[0] - 0xf0) as usize] as u16;
-                self.pc += 2;
+                self.I = self
+                    .I
+                    .wrapping_add(self.v_register[(opcode.1[0] - 0xf0) as usize] as u16);
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::LD_FV => {
-                self.I = 0x050 + (self.v_register[(opcode.1[0] - 0xf0) as usize] * 5) as u16;
-                self.pc += 2;
+                // the low nibble picks the digit, so any VX points into the font
+                let digit = (self.v_register[(opcode.1[0] - 0xf0) as usize] & 0x0f) as u16;
+                self.I = 0x050 + digit * 5;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::LD_BV => {
+                self.check_memory_range(self.I as usize, 3)?;
                 self.memory[self.I as usize] = self.v_register[(opcode.1[0] - 0xf0) as usize] / 100;
                 let buf: u8 = self.v_register[(opcode.1[0] - 0xf0) as usize] % 100;
                 self.memory[(self.I + 1) as usize] = buf / 10;
                 self.memory[(self.I + 2) as usize] = buf % 10;
-                self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::LD_IV => {
+                self.check_memory_range(self.I as usize, (opcode.1[0] - 0xf0) as usize + 1)?;
                 for register in 0..=(opcode.1[0] - 0xf0) {
                     self.memory[(self.I + register as u16) as usize] =
                         self.v_register[register as usize]
                 }
-                self.pc += 2;
+                if self.quirks.load_store_increments_i {
+                    self.I = self.I.wrapping_add((opcode.1[0] - 0xf0) as u16 + 1);
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             Opcode::LD_VI => {
+                self.check_memory_range(self.I as usize, (opcode.1[0] - 0xf0) as usize + 1)?;
                 for register in 0..=(opcode.1[0] - 0xf0) {
                     self.v_register[register as usize] =
                         self.memory[(self.I + register as u16) as usize]
                 }
-                self.pc += 2;
+                if self.quirks.load_store_increments_i {
+                    self.I = self.I.wrapping_add((opcode.1[0] - 0xf0) as u16 + 1);
+                }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::SCD => {
+                self.scroll(0, (opcode.1[1] & 0x0f) as isize);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::SCR => {
+                self.scroll(4, 0);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::SCL => {
+                self.scroll(-4, 0);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::EXIT => {
+                self.halted = true;
+            }
+            Opcode::LOW => {
+                self.set_hires(false);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::HIGH => {
+                self.set_hires(true);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::LD_HFV => {
+                // the low nibble picks the digit like LD_FV, but there are only big
+                // glyphs for 0 to 9 so A to F stay on 9 instead of reading past them
+                let digit = (self.v_register[(opcode.1[0] - 0xf0) as usize] & 0x0f).min(9) as u16;
+                self.I = BIG_FONT_ADDRESS as u16 + digit * 10;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::LD_RV => {
+                for register in 0..=(opcode.1[0] - 0xf0) as usize {
+                    self.rpl_flags[register] = self.v_register[register];
+                }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::LD_VR => {
+                for register in 0..=(opcode.1[0] - 0xf0) as usize {
+                    self.v_register[register] = self.rpl_flags[register];
+                }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::SCU => {
+                self.scroll(0, -((opcode.1[1] & 0x0f) as isize));
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::LD_IVV => {
+                let registers = Chip8::register_range(opcode.1);
+                self.check_memory_range(self.I as usize, registers.len())?;
+                for (offset, register) in registers.iter().enumerate() {
+                    self.memory[self.I as usize + offset] = self.v_register[*register];
+                }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::LD_VVI => {
+                let registers = Chip8::register_range(opcode.1);
+                self.check_memory_range(self.I as usize, registers.len())?;
+                for (offset, register) in registers.iter().enumerate() {
+                    self.v_register[*register] = self.memory[self.I as usize + offset];
+                }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::LD_IL => {
+                // the address is the whole word after the instruction
+                let address = self.pc as usize + 2;
+                self.check_memory_range(address, 2)?;
+                self.I = (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16;
+                self.pc = self.pc.wrapping_add(4);
+            }
+            Opcode::AUDIO => {
+                self.check_memory_range(self.I as usize, 16)?;
+                let mut bits = [0u8; 16];
+                bits.copy_from_slice(&self.memory[self.I as usize..self.I as usize + 16]);
+                self.audio_pattern = Some(bits);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::PITCH => {
+                self.pitch = self.v_register[(opcode.1[0] - 0xf0) as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Opcode::PLANE => {
+                self.planes = (opcode.1[0] - 0xf0) & 0x03;
+                self.pc = self.pc.wrapping_add(2);
             }
         }
         //self.pc += 2; //this may need to move as i think jump instructions probably should not increment past the location or those just need to coutner act by subtracting 2
+        Ok(())
+    }
+    fn set_hires(&mut self, hires: bool) {
+        // switching resolution leaves the old picture meaningless so it is cleared
+        self.hires = hires;
+        self.gfx = [0; HIRES_WIDTH * HIRES_HEIGHT];
+        self.draw_flag = true;
+    }
+
+    fn xy_coord(&self, x: u32, y: u32) -> usize {
+        // this returns the index of a value when give its xy corrds, wrapping around the screen edges
+        let (width, height) = self.resolution();
+        let final_x = x as usize % width;
+        let final_y = y as usize % height;
+        (width * final_y) + final_x
+    }
+
+    fn scroll(&mut self, right: isize, down: isize) {
+        // moves the selected planes, pixels scrolled in from the edges are blank
+        let (width, height) = self.resolution();
+        let old = self.gfx;
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let (from_x, from_y) = (x - right, y - down);
+                let moved = if from_x >= 0
+                    && from_y >= 0
+                    && from_x < width as isize
+                    && from_y < height as isize
+                {
+                    old[from_y as usize * width + from_x as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.gfx[y as usize * width + x as usize];
+                *pixel = (*pixel & !self.planes) | (moved & self.planes);
+            }
+        }
+        self.draw_flag = true;
+    }
+
+    fn skip_next_instruction(&mut self) {
+        // the xo chip long load is 4 bytes so skipping it has to jump over both words
+        let next = self.pc as usize + 2;
+        if self.platform == Platform::XoChip
+            && next + 1 < self.memory.len()
+            && self.memory[next] == 0xf0
+            && self.memory[next + 1] == 0x00
+        {
+            self.pc = self.pc.wrapping_add(6);
+        } else {
+            self.pc = self.pc.wrapping_add(4);
+        }
     }
+
+    fn register_range(opcode: [u8; 2]) -> Vec<usize> {
+        // 5XY2 and 5XY3 go from VX to VY, backwards when X is bigger
+        let x = (opcode[0] & 0x0f) as usize;
+        let y = (opcode[1] >> 4) as usize;
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
+    fn shift_source(&self, opcode: [u8; 2]) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.v_register[(opcode[1] >> 4) as usize]
+        } else {
+            self.v_register[(opcode[0] - 0x80) as usize]
+        }
+    }
+
     pub fn decrease_timers(&mut self) {
         if self.delay_timer != 0 {
             self.delay_timer -= 1;
@@ -716,23 +1149,6 @@ impl Chip8 {
     }
 }
 
-fn xy_coord(x: u32, y: u32) -> u32 {
-    // should maybe set a value for screen width in case it gets changed. this returns the index of a value when give its xy corrds
-    let mut final_x = 0;
-    let mut final_y = 0;
-    if x >= 64 {
-        final_x = x - 64;
-    } else {
-        final_x = x;
-    }
-    if y >= 32 {
-        final_y = y - 32;
-    } else {
-        final_y = y
-    }
-    (64 * final_y) + final_x
-}
-
 fn highest_hex_value(test_value: u8, wanted_value: u8) -> bool {
     //the wanted value can have any second byte value this only check the left most hex byte
     //this is done with an xor and if it matches it gets the first value to be zero and returns true
@@ -756,6 +1172,20 @@ pub fn decode_opcode(opcode: [u8; 2]) -> Option<(Opcode, [u8; 2])> {
             return Some((Opcode::CLS, opcode));
         } else if both_hex_values(opcode[1], 0xee) {
             return Some((Opcode::RET, opcode));
+        } else if opcode[0] == 0x00 && highest_hex_value(opcode[1], 0xc0) {
+            return Some((Opcode::SCD, opcode));
+        } else if opcode[0] == 0x00 && highest_hex_value(opcode[1], 0xd0) {
+            return Some((Opcode::SCU, opcode));
+        } else if opcode[0] == 0x00 && both_hex_values(opcode[1], 0xfb) {
+            return Some((Opcode::SCR, opcode));
+        } else if opcode[0] == 0x00 && both_hex_values(opcode[1], 0xfc) {
+            return Some((Opcode::SCL, opcode));
+        } else if opcode[0] == 0x00 && both_hex_values(opcode[1], 0xfd) {
+            return Some((Opcode::EXIT, opcode));
+        } else if opcode[0] == 0x00 && both_hex_values(opcode[1], 0xfe) {
+            return Some((Opcode::LOW, opcode));
+        } else if opcode[0] == 0x00 && both_hex_values(opcode[1], 0xff) {
+            return Some((Opcode::HIGH, opcode));
         }
         return Some((Opcode::SYS, opcode));
     } else if highest_hex_value(opcode[0], 0x10) {
@@ -767,7 +1197,14 @@ pub fn decode_opcode(opcode: [u8; 2]) -> Option<(Opcode, [u8; 2])> {
     } else if highest_hex_value(opcode[0], 0x40) {
         return Some((Opcode::SNE_VB, opcode));
     } else if highest_hex_value(opcode[0], 0x50) {
-        return Some((Opcode::SE_VV, opcode));
+        if lowest_hex_value(opcode[1], 0x00) {
+            return Some((Opcode::SE_VV, opcode));
+        } else if lowest_hex_value(opcode[1], 0x02) {
+            return Some((Opcode::LD_IVV, opcode));
+        } else if lowest_hex_value(opcode[1], 0x03) {
+            return Some((Opcode::LD_VVI, opcode));
+        }
+        return None;
     } else if highest_hex_value(opcode[0], 0x60) {
         return Some((Opcode::LD_VB, opcode));
     } else if highest_hex_value(opcode[0], 0x70) {
@@ -811,7 +1248,15 @@ pub fn decode_opcode(opcode: [u8; 2]) -> Option<(Opcode, [u8; 2])> {
         }
         return None;
     } else if highest_hex_value(opcode[0], 0xF0) {
-        if both_hex_values(opcode[1], 0x07) {
+        if both_hex_values(opcode[0], 0xf0) && both_hex_values(opcode[1], 0x00) {
+            return Some((Opcode::LD_IL, opcode));
+        } else if both_hex_values(opcode[1], 0x01) {
+            return Some((Opcode::PLANE, opcode));
+        } else if both_hex_values(opcode[0], 0xf0) && both_hex_values(opcode[1], 0x02) {
+            return Some((Opcode::AUDIO, opcode));
+        } else if both_hex_values(opcode[1], 0x3a) {
+            return Some((Opcode::PITCH, opcode));
+        } else if both_hex_values(opcode[1], 0x07) {
             return Some((Opcode::LD_VDT, opcode));
         } else if both_hex_values(opcode[1], 0x0a) {
             return Some((Opcode::LD_VK, opcode));
@@ -829,6 +1274,12 @@ pub fn decode_opcode(opcode: [u8; 2]) -> Option<(Opcode, [u8; 2])> {
             return Some((Opcode::LD_IV, opcode));
         } else if both_hex_values(opcode[1], 0x65) {
             return Some((Opcode::LD_VI, opcode));
+        } else if both_hex_values(opcode[1], 0x30) {
+            return Some((Opcode::LD_HFV, opcode));
+        } else if both_hex_values(opcode[1], 0x75) {
+            return Some((Opcode::LD_RV, opcode));
+        } else if both_hex_values(opcode[1], 0x85) {
+            return Some((Opcode::LD_VR, opcode));
         }
         return None;
     }
@@ -839,6 +1290,636 @@ pub fn decode_opcode(opcode: [u8; 2]) -> Option<(Opcode, [u8; 2])> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::audio::RecordingAudio;
+    use crate::display::MemoryDisplay;
+
+    #[test]
+    fn set_key_pressed_released() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.set_key(KeyEvent::Pressed(0xb));
+        assert!(test_chip8.key[0xb]);
+        test_chip8.set_key(KeyEvent::Released(0xb));
+        assert!(!test_chip8.key[0xb]);
+    }
+
+    #[test]
+    fn update_display_only_when_drawn() {
+        let mut test_chip8 = Chip8::init();
+        let mut display = MemoryDisplay::new();
+        test_chip8.update_display(&mut display);
+        test_chip8.update_display(&mut display);
+        assert_eq!(display.frames_drawn, 1);
+        test_chip8
+            .execute_opcode((Opcode::CLS, [0x00, 0xe0]))
+            .unwrap();
+        test_chip8.update_display(&mut display);
+        assert_eq!(display.frames_drawn, 2);
+        assert_eq!(display.width, SCREEN_WIDTH);
+    }
+
+    #[test]
+    fn update_audio_follows_sound_timer() {
+        let mut test_chip8 = Chip8::init();
+        let mut audio = RecordingAudio::new();
+        test_chip8.v_register[3] = 2;
+        test_chip8
+            .execute_opcode((Opcode::LD_STV, [0xf3, 0x18]))
+            .unwrap();
+        for _ in 0..3 {
+            test_chip8.update_audio(&mut audio);
+            test_chip8.decrease_timers();
+        }
+        assert_eq!(audio.frames, vec![true, true, false]);
+    }
+
+    #[test]
+    fn execute_reports_vip_cycles() {
+        let mut test_chip8 = Chip8::init();
+        let falls_through = test_chip8
+            .execute_opcode((Opcode::SE_VB, [0x30, 0x01]))
+            .unwrap();
+        let skips = test_chip8
+            .execute_opcode((Opcode::SE_VB, [0x30, 0x00]))
+            .unwrap();
+        assert_eq!(skips, falls_through + 4);
+    }
+
+    #[test]
+    fn run_frame_cycles_spends_a_budget() {
+        let mut test_chip8 = Chip8::init();
+        // ADD V0, 1 then jump back, 50 and 52 cycles
+        test_chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut keypad = crate::input::ScriptedKeypad::new(Vec::new());
+        test_chip8.run_frame_cycles(&mut keypad, 1000).unwrap();
+        // 10 rounds is 1020 cycles, the 20 over come out of the next frame
+        assert_eq!(test_chip8.v_register[0], 10);
+        test_chip8.run_frame_cycles(&mut keypad, 1000).unwrap();
+        assert_eq!(test_chip8.v_register[0], 20);
+
+        // a sprite ends the frame like the VIP waiting for the display interrupt
+        let mut test_chip8 = Chip8::init();
+        test_chip8
+            .load_rom(&[0x70, 0x01, 0xd1, 0x11, 0x12, 0x00])
+            .unwrap();
+        test_chip8.run_frame_cycles(&mut keypad, 3000).unwrap();
+        assert_eq!(test_chip8.v_register[0], 1);
+        test_chip8.run_frame_cycles(&mut keypad, 3000).unwrap();
+        assert_eq!(test_chip8.v_register[0], 2);
+    }
+
+    #[test]
+    fn step_unknown_opcode() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.load_rom(&[0x80, 0x0f]).unwrap();
+        match test_chip8.step() {
+            Err(Chip8Error::UnknownOpcode { pc, opcode }) => {
+                assert_eq!(pc, 0x200);
+                assert_eq!(opcode, [0x80, 0x0f]);
+            }
+            other => panic!("expected unknown opcode, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn step_pc_out_of_range() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.pc = 0xfff;
+        assert!(matches!(
+            test_chip8.step(),
+            Err(Chip8Error::MemoryOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn load_rom_too_large() {
+        let mut test_chip8 = Chip8::init();
+        assert!(matches!(
+            test_chip8.load_rom(&[0; 0xe01]),
+            Err(Chip8Error::RomTooLarge { size: 0xe01, .. })
+        ));
+    }
+
+    #[test]
+    fn load_game_missing_file() {
+        let mut test_chip8 = Chip8::init();
+        assert!(matches!(
+            test_chip8.load_game("games/does_not_exist.ch8"),
+            Err(Chip8Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn load_game_octo_source() {
+        let path = std::env::temp_dir().join(format!("chip8_{}.8o", std::process::id()));
+        fs::write(&path, ": main\n  v0 := 7\n  return\n").unwrap();
+        let mut test_chip8 = Chip8::init();
+        let loaded = test_chip8.load_game(&path);
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        test_chip8.step().unwrap();
+        test_chip8.step().unwrap();
+        assert_eq!(test_chip8.v_register[0], 7);
+        let error = test_chip8.step().unwrap_err();
+        assert_eq!(
+            test_chip8.describe_error(&error),
+            "return with an empty stack at 0x204 (line 3)"
+        );
+    }
+
+    #[test]
+    fn load_game_applies_the_rom_database() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8
+            .load_game(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/roms/memory.ch8"
+            ))
+            .unwrap();
+        assert_eq!(test_chip8.quirks, Quirks::COSMAC_VIP);
+        let info = test_chip8.rom_info().unwrap();
+        assert_eq!(info.title, "Memory quirk test");
+        // loading raw bytes forgets it again
+        test_chip8.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(test_chip8.rom_info().is_none());
+    }
+
+    #[test]
+    fn load_game_on_a_chosen_platform() {
+        // bigger than chip8 memory and not in the database
+        let path = std::env::temp_dir().join(format!("chip8_{}.ch8", std::process::id()));
+        fs::write(&path, vec![0x12; 0x1000]).unwrap();
+        let mut test_chip8 = Chip8::init();
+        let loaded = test_chip8.load_game_on(&path, Some(Platform::XoChip));
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        assert_eq!(test_chip8.platform(), Platform::XoChip);
+
+        // a platform given wins over the database, which still names the rom
+        test_chip8
+            .load_game_on(
+                concat!(env!("CARGO_MANIFEST_DIR"), "/tests/roms/memory.ch8"),
+                Some(Platform::SuperChip),
+            )
+            .unwrap();
+        assert_eq!(test_chip8.platform(), Platform::SuperChip);
+        assert_eq!(test_chip8.quirks, Quirks::SUPER_CHIP);
+        assert_eq!(test_chip8.rom_info().unwrap().title, "Memory quirk test");
+    }
+
+    #[test]
+    fn stack_lists_the_calls_oldest_first() {
+        let mut test_chip8 = Chip8::init();
+        assert!(test_chip8.stack().is_empty());
+        test_chip8
+            .execute_opcode((Opcode::CALL, [0x22, 0x02]))
+            .unwrap();
+        test_chip8
+            .execute_opcode((Opcode::CALL, [0x22, 0x08]))
+            .unwrap();
+        assert_eq!(test_chip8.stack(), [0x200, 0x202]);
+    }
+
+    #[test]
+    fn execute_ret_empty_stack() {
+        let mut test_chip8 = Chip8::init();
+        assert!(matches!(
+            test_chip8.execute_opcode((Opcode::RET, [0x00, 0xee])),
+            Err(Chip8Error::StackUnderflow { pc: 0x200 })
+        ));
+    }
+
+    #[test]
+    fn execute_call_stack_overflow() {
+        let mut test_chip8 = Chip8::init();
+        for _ in 0..16 {
+            test_chip8
+                .execute_opcode((Opcode::CALL, [0x22, 0x00]))
+                .unwrap();
+        }
+        assert!(matches!(
+            test_chip8.execute_opcode((Opcode::CALL, [0x22, 0x00])),
+            Err(Chip8Error::StackOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn pc_wraps_at_the_top_of_xo_chip_memory() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.set_platform(Platform::XoChip);
+        test_chip8.pc = 0xfffe;
+        test_chip8
+            .execute_opcode((Opcode::CLS, [0x00, 0xe0]))
+            .unwrap();
+        assert_eq!(test_chip8.pc, 0);
+        test_chip8.pc = 0xfffe;
+        test_chip8
+            .execute_opcode((Opcode::SE_VB, [0x30, 0x00]))
+            .unwrap();
+        assert_eq!(test_chip8.pc, 2);
+        // the address word of a long load at 0xfffc is the last one in memory
+        test_chip8.pc = 0xfffc;
+        test_chip8
+            .execute_opcode((Opcode::LD_IL, [0xf0, 0x00]))
+            .unwrap();
+        assert_eq!(test_chip8.pc, 0);
+        // and stepping from 0xffff fails instead of reading past the end
+        test_chip8.pc = 0xffff;
+        assert!(matches!(
+            test_chip8.step(),
+            Err(Chip8Error::MemoryOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn execute_ld_vi_out_of_range() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.I = 0xffe;
+        assert!(matches!(
+            test_chip8.execute_opcode((Opcode::LD_VI, [0xf3, 0x65])),
+            Err(Chip8Error::MemoryOutOfRange {
+                address: 0x1001,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn execute_drw_collision() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.I = 0x050; // font sprite for 0
+        test_chip8.v_register[0] = 2;
+        test_chip8.v_register[1] = 3;
+        test_chip8
+            .execute_opcode((Opcode::DRW, [0xd0, 0x15]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[0xf], 0);
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(2, 3)], 1);
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(3, 4)], 0);
+        test_chip8
+            .execute_opcode((Opcode::DRW, [0xd0, 0x15]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[0xf], 1);
+        assert!(test_chip8.gfx.iter().all(|pixel| *pixel == 0));
+    }
+
+    #[test]
+    fn execute_drw_wrap_and_clip() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.I = 0x050;
+        test_chip8.v_register[0] = 62;
+        test_chip8.v_register[1] = 0;
+        test_chip8
+            .execute_opcode((Opcode::DRW, [0xd0, 0x11]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(1, 0)], 1);
+
+        test_chip8.gfx = [0; HIRES_WIDTH * HIRES_HEIGHT];
+        test_chip8.quirks = Quirks::COSMAC_VIP;
+        test_chip8
+            .execute_opcode((Opcode::DRW, [0xd0, 0x11]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(63, 0)], 1);
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(1, 0)], 0);
+    }
+
+    #[test]
+    fn quirk_shift_uses_vy() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.quirks.shift_uses_vy = true;
+        test_chip8.v_register[4] = 0x81;
+        test_chip8
+            .execute_opcode((Opcode::SHR, [0x88, 0x46]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[8], 0x40);
+        assert_eq!(test_chip8.v_register[0xf], 1);
+        test_chip8
+            .execute_opcode((Opcode::SHL, [0x88, 0x4e]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[8], 0x02);
+        assert_eq!(test_chip8.v_register[0xf], 1);
+    }
+
+    #[test]
+    fn default_quirks_shift_in_place_and_modern_shifts_vy() {
+        let mut test_chip8 = Chip8::init();
+        assert_eq!(test_chip8.quirks, Quirks::default());
+        test_chip8.v_register[4] = 0x81;
+        test_chip8.v_register[8] = 0x10;
+        test_chip8
+            .execute_opcode((Opcode::SHR, [0x88, 0x46]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[8], 0x08);
+
+        test_chip8.quirks = Quirks::MODERN;
+        test_chip8
+            .execute_opcode((Opcode::SHR, [0x88, 0x46]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[8], 0x40);
+        assert_eq!(test_chip8.v_register[0xf], 1);
+    }
+
+    #[test]
+    fn quirk_load_store_increments_i() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.quirks.load_store_increments_i = true;
+        test_chip8.I = 0x300;
+        test_chip8
+            .execute_opcode((Opcode::LD_IV, [0xf2, 0x55]))
+            .unwrap();
+        assert_eq!(test_chip8.I, 0x303);
+        test_chip8
+            .execute_opcode((Opcode::LD_VI, [0xf0, 0x65]))
+            .unwrap();
+        assert_eq!(test_chip8.I, 0x304);
+    }
+
+    #[test]
+    fn quirk_logic_resets_vf() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.quirks.logic_resets_vf = true;
+        test_chip8.v_register[0xf] = 1;
+        test_chip8
+            .execute_opcode((Opcode::OR, [0x88, 0x41]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[0xf], 0);
+    }
+
+    #[test]
+    fn quirk_jump_uses_vx() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.quirks.jump_uses_vx = true;
+        test_chip8.v_register[0] = 0x1;
+        test_chip8.v_register[4] = 0xa;
+        test_chip8
+            .execute_opcode((Opcode::JP_VA, [0xb4, 0x30]))
+            .unwrap();
+        assert_eq!(test_chip8.pc, 0x43a);
+    }
+
+    #[test]
+    fn decode_super_chip() {
+        assert_eq!(decode_opcode([0x00, 0xc5]).unwrap().0, Opcode::SCD);
+        assert_eq!(decode_opcode([0x00, 0xfb]).unwrap().0, Opcode::SCR);
+        assert_eq!(decode_opcode([0x00, 0xfc]).unwrap().0, Opcode::SCL);
+        assert_eq!(decode_opcode([0x00, 0xfd]).unwrap().0, Opcode::EXIT);
+        assert_eq!(decode_opcode([0x00, 0xfe]).unwrap().0, Opcode::LOW);
+        assert_eq!(decode_opcode([0x00, 0xff]).unwrap().0, Opcode::HIGH);
+        assert_eq!(decode_opcode([0xf3, 0x30]).unwrap().0, Opcode::LD_HFV);
+        assert_eq!(decode_opcode([0xf3, 0x75]).unwrap().0, Opcode::LD_RV);
+        assert_eq!(decode_opcode([0xf3, 0x85]).unwrap().0, Opcode::LD_VR);
+        assert_eq!(decode_opcode([0x01, 0xc5]).unwrap().0, Opcode::SYS);
+    }
+
+    #[test]
+    fn execute_high_low() {
+        let mut test_chip8 = Chip8::init();
+        let mut display = MemoryDisplay::new();
+        test_chip8
+            .execute_opcode((Opcode::HIGH, [0x00, 0xff]))
+            .unwrap();
+        test_chip8.update_display(&mut display);
+        assert_eq!((display.width, display.height), (128, 64));
+        test_chip8
+            .execute_opcode((Opcode::LOW, [0x00, 0xfe]))
+            .unwrap();
+        test_chip8.update_display(&mut display);
+        assert_eq!((display.width, display.height), (64, 32));
+    }
+
+    #[test]
+    fn execute_drw_16x16() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8
+            .execute_opcode((Opcode::HIGH, [0x00, 0xff]))
+            .unwrap();
+        test_chip8.I = 0x300;
+        test_chip8.memory[0x300] = 0x80;
+        test_chip8.memory[0x301] = 0x01;
+        test_chip8.memory[0x31f] = 0x01;
+        test_chip8.v_register[0] = 100;
+        test_chip8.v_register[1] = 40;
+        test_chip8
+            .execute_opcode((Opcode::DRW, [0xd0, 0x10]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(100, 40)], 1);
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(115, 40)], 1);
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(115, 55)], 1);
+        assert_eq!(
+            test_chip8.gfx.iter().filter(|pixel| **pixel == 1).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn execute_scroll() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.gfx[0] = 1;
+        test_chip8
+            .execute_opcode((Opcode::SCD, [0x00, 0xc3]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(0, 3)], 1);
+        test_chip8
+            .execute_opcode((Opcode::SCR, [0x00, 0xfb]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(4, 3)], 1);
+        test_chip8
+            .execute_opcode((Opcode::SCL, [0x00, 0xfc]))
+            .unwrap();
+        test_chip8
+            .execute_opcode((Opcode::SCL, [0x00, 0xfc]))
+            .unwrap();
+        assert!(test_chip8.gfx.iter().all(|pixel| *pixel == 0));
+    }
+
+    #[test]
+    fn execute_exit_halts() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.load_rom(&[0x00, 0xfd, 0x60, 0x01]).unwrap();
+        test_chip8.step().unwrap();
+        test_chip8.step().unwrap();
+        assert!(test_chip8.is_halted());
+        assert_eq!(test_chip8.v_register[0], 0);
+    }
+
+    #[test]
+    fn spinning_on_jump_to_self() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.load_rom(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        assert!(!test_chip8.is_spinning());
+        test_chip8.step().unwrap();
+        assert!(test_chip8.is_spinning());
+        test_chip8.pc = 0xfff;
+        assert!(!test_chip8.is_spinning());
+    }
+
+    #[test]
+    fn execute_ld_hfv() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.v_register[3] = 2;
+        test_chip8
+            .execute_opcode((Opcode::LD_HFV, [0xf3, 0x30]))
+            .unwrap();
+        assert_eq!(test_chip8.I, 0x0b4);
+        assert_eq!(test_chip8.memory[0x0b4], 0x3e);
+    }
+
+    #[test]
+    fn execute_ld_hfv_stays_in_the_big_font() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.v_register[3] = 0x13;
+        test_chip8
+            .execute_opcode((Opcode::LD_HFV, [0xf3, 0x30]))
+            .unwrap();
+        assert_eq!(test_chip8.I, BIG_FONT_ADDRESS as u16 + 30);
+        test_chip8.v_register[3] = 0xff;
+        test_chip8
+            .execute_opcode((Opcode::LD_HFV, [0xf3, 0x30]))
+            .unwrap();
+        assert_eq!(test_chip8.I, BIG_FONT_ADDRESS as u16 + 90);
+    }
+
+    #[test]
+    fn execute_rpl_flags() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.v_register[0] = 7;
+        test_chip8.v_register[1] = 9;
+        test_chip8
+            .execute_opcode((Opcode::LD_RV, [0xf1, 0x75]))
+            .unwrap();
+        test_chip8.v_register = [0; 16];
+        test_chip8
+            .execute_opcode((Opcode::LD_VR, [0xf1, 0x85]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[0..2], [7, 9]);
+    }
+
+    #[test]
+    fn decode_xo_chip() {
+        assert_eq!(decode_opcode([0x00, 0xd4]).unwrap().0, Opcode::SCU);
+        assert_eq!(decode_opcode([0x51, 0x22]).unwrap().0, Opcode::LD_IVV);
+        assert_eq!(decode_opcode([0x51, 0x23]).unwrap().0, Opcode::LD_VVI);
+        assert_eq!(decode_opcode([0xf0, 0x00]).unwrap().0, Opcode::LD_IL);
+        assert_eq!(decode_opcode([0xf2, 0x01]).unwrap().0, Opcode::PLANE);
+        assert_eq!(decode_opcode([0xf0, 0x02]).unwrap().0, Opcode::AUDIO);
+        assert_eq!(decode_opcode([0xf4, 0x3a]).unwrap().0, Opcode::PITCH);
+        assert_eq!(decode_opcode([0x51, 0x21]), None);
+    }
+
+    #[test]
+    fn set_platform_xo_chip_memory() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.set_platform(Platform::XoChip);
+        assert_eq!(test_chip8.quirks, Quirks::XO_CHIP);
+        test_chip8.load_rom(&[0; 0x8000]).unwrap();
+        test_chip8.I = 0xfffe;
+        test_chip8
+            .execute_opcode((Opcode::LD_IV, [0xf1, 0x55]))
+            .unwrap();
+        test_chip8.I = 0xffff;
+        assert!(matches!(
+            test_chip8.execute_opcode((Opcode::LD_IV, [0xf1, 0x55])),
+            Err(Chip8Error::MemoryOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn execute_ld_il() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.set_platform(Platform::XoChip);
+        test_chip8.load_rom(&[0xf0, 0x00, 0x12, 0x34]).unwrap();
+        test_chip8.step().unwrap();
+        assert_eq!(test_chip8.I, 0x1234);
+        assert_eq!(test_chip8.pc, 0x204);
+    }
+
+    #[test]
+    fn skip_over_ld_il() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.set_platform(Platform::XoChip);
+        test_chip8
+            .load_rom(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34, 0x60, 0x01])
+            .unwrap();
+        test_chip8.step().unwrap();
+        assert_eq!(test_chip8.pc, 0x206);
+    }
+
+    #[test]
+    fn execute_register_range() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.I = 0x300;
+        test_chip8.v_register[2] = 0xa;
+        test_chip8.v_register[3] = 0xb;
+        test_chip8.v_register[4] = 0xc;
+        test_chip8
+            .execute_opcode((Opcode::LD_IVV, [0x54, 0x22]))
+            .unwrap();
+        assert_eq!(test_chip8.memory[0x300..0x303], [0xc, 0xb, 0xa]);
+        assert_eq!(test_chip8.I, 0x300);
+        test_chip8
+            .execute_opcode((Opcode::LD_VVI, [0x57, 0x92]))
+            .unwrap();
+        assert_eq!(test_chip8.v_register[7..10], [0xc, 0xb, 0xa]);
+    }
+
+    #[test]
+    fn execute_drw_both_planes() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8
+            .execute_opcode((Opcode::PLANE, [0xf3, 0x01]))
+            .unwrap();
+        test_chip8.I = 0x300;
+        test_chip8.memory[0x300] = 0x80;
+        test_chip8.memory[0x301] = 0xc0;
+        test_chip8
+            .execute_opcode((Opcode::DRW, [0xd0, 0x01]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[0], 3);
+        assert_eq!(test_chip8.gfx[1], 2);
+        assert_eq!(test_chip8.v_register[0xf], 0);
+
+        test_chip8
+            .execute_opcode((Opcode::PLANE, [0xf2, 0x01]))
+            .unwrap();
+        test_chip8
+            .execute_opcode((Opcode::CLS, [0x00, 0xe0]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[0], 1);
+        assert_eq!(test_chip8.gfx[1], 0);
+    }
+
+    #[test]
+    fn execute_audio_pattern() {
+        let mut test_chip8 = Chip8::init();
+        let mut audio = RecordingAudio::new();
+        test_chip8.update_audio(&mut audio);
+        assert_eq!(audio.pattern, None);
+        test_chip8.I = 0x300;
+        test_chip8.memory[0x300] = 0xf0;
+        test_chip8.memory[0x30f] = 0x0f;
+        test_chip8.v_register[2] = 112;
+        test_chip8
+            .execute_opcode((Opcode::AUDIO, [0xf0, 0x02]))
+            .unwrap();
+        test_chip8
+            .execute_opcode((Opcode::PITCH, [0xf2, 0x3a]))
+            .unwrap();
+        test_chip8.update_audio(&mut audio);
+        let pattern = audio.pattern.unwrap();
+        assert_eq!(pattern.bits[0], 0xf0);
+        assert_eq!(pattern.bits[15], 0x0f);
+        assert_eq!(pattern.pitch, 112);
+    }
+
+    #[test]
+    fn execute_scroll_up() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.gfx[test_chip8.xy_coord(5, 4)] = 1;
+        test_chip8
+            .execute_opcode((Opcode::SCU, [0x00, 0xd3]))
+            .unwrap();
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(5, 1)], 1);
+        assert_eq!(test_chip8.gfx[test_chip8.xy_coord(5, 4)], 0);
+    }
 
     #[test]
     fn decode_ret() {
@@ -850,7 +1931,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.sp += 1;
         test_chip8.stack[test_chip8.sp as usize] = 32;
-        test_chip8.execute_opcode((Opcode::RET, [0x00, 0xee]));
+        test_chip8
+            .execute_opcode((Opcode::RET, [0x00, 0xee]))
+            .unwrap();
         assert_eq!(test_chip8.sp, 0);
         assert_eq!(test_chip8.pc, 34);
     }
@@ -863,7 +1946,9 @@ mod tests {
     #[test]
     fn execute_jp_a() {
         let mut test_chip8 = Chip8::init();
-        test_chip8.execute_opcode((Opcode::JP_A, [0x13, 0x33]));
+        test_chip8
+            .execute_opcode((Opcode::JP_A, [0x13, 0x33]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x0333);
     }
 
@@ -877,7 +1962,9 @@ mod tests {
     fn execute_call() {
         let mut test_chip8 = Chip8::init();
         test_chip8.pc = 0x44;
-        test_chip8.execute_opcode((Opcode::CALL, [0x22, 0x22]));
+        test_chip8
+            .execute_opcode((Opcode::CALL, [0x22, 0x22]))
+            .unwrap();
         assert_eq!(test_chip8.sp, 1);
         assert_eq!(test_chip8.stack[test_chip8.sp as usize], 0x44);
         assert_eq!(test_chip8.pc, 0x222);
@@ -893,7 +1980,9 @@ mod tests {
     fn execute_se_vb() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[0xa] = 0xef;
-        test_chip8.execute_opcode((Opcode::SE_VB, [0x3a, 0xef]));
+        test_chip8
+            .execute_opcode((Opcode::SE_VB, [0x3a, 0xef]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x204);
     }
 
@@ -907,7 +1996,9 @@ mod tests {
     fn execute_sne_vb() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[0xa] = 0xfe;
-        test_chip8.execute_opcode((Opcode::SNE_VB, [0x4a, 0xef]));
+        test_chip8
+            .execute_opcode((Opcode::SNE_VB, [0x4a, 0xef]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x204);
     }
 
@@ -922,7 +2013,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[0] = 4;
         test_chip8.v_register[0xa] = 4;
-        test_chip8.execute_opcode((Opcode::SE_VV, [0x50, 0xa0]));
+        test_chip8
+            .execute_opcode((Opcode::SE_VV, [0x50, 0xa0]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x204);
     }
 
@@ -935,7 +2028,9 @@ mod tests {
     #[test]
     fn execute_ld_vb() {
         let mut test_chip8 = Chip8::init();
-        test_chip8.execute_opcode((Opcode::LD_VB, [0x65, 0xe3]));
+        test_chip8
+            .execute_opcode((Opcode::LD_VB, [0x65, 0xe3]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[5], 0xe3);
     }
 
@@ -948,7 +2043,9 @@ mod tests {
     #[test]
     fn execute_add_vb_no_overflow() {
         let mut test_chip8 = Chip8::init();
-        test_chip8.execute_opcode((Opcode::ADD_VB, [0x77, 0x25]));
+        test_chip8
+            .execute_opcode((Opcode::ADD_VB, [0x77, 0x25]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[7], 0x25);
     }
 
@@ -956,7 +2053,9 @@ mod tests {
     fn execute_add_vb_overflow() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[7] = 0xff;
-        test_chip8.execute_opcode((Opcode::ADD_VB, [0x77, 0x25]));
+        test_chip8
+            .execute_opcode((Opcode::ADD_VB, [0x77, 0x25]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[7], 0x24);
     }
 
@@ -970,7 +2069,9 @@ mod tests {
     fn execute_ld_vv() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[4] = 0xff;
-        test_chip8.execute_opcode((Opcode::LD_VV, [0x88, 0x40]));
+        test_chip8
+            .execute_opcode((Opcode::LD_VV, [0x88, 0x40]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0xff);
     }
 
@@ -985,7 +2086,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xaa;
         test_chip8.v_register[4] = 0x99;
-        test_chip8.execute_opcode((Opcode::OR, [0x88, 0x41]));
+        test_chip8
+            .execute_opcode((Opcode::OR, [0x88, 0x41]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0xbb);
     }
 
@@ -1000,7 +2103,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xaa;
         test_chip8.v_register[4] = 0xc3;
-        test_chip8.execute_opcode((Opcode::AND, [0x88, 0x42]));
+        test_chip8
+            .execute_opcode((Opcode::AND, [0x88, 0x42]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0x82);
     }
 
@@ -1015,7 +2120,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xaa;
         test_chip8.v_register[4] = 0xc3;
-        test_chip8.execute_opcode((Opcode::XOR, [0x88, 0x43]));
+        test_chip8
+            .execute_opcode((Opcode::XOR, [0x88, 0x43]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0x69);
     }
 
@@ -1030,7 +2137,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0x0a;
         test_chip8.v_register[4] = 0xa0;
-        test_chip8.execute_opcode((Opcode::ADD_VV, [0x88, 0x44]));
+        test_chip8
+            .execute_opcode((Opcode::ADD_VV, [0x88, 0x44]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0xaa);
         assert_eq!(test_chip8.v_register[0xf], 0x00);
     }
@@ -1040,7 +2149,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xaa;
         test_chip8.v_register[4] = 0xa0;
-        test_chip8.execute_opcode((Opcode::ADD_VV, [0x88, 0x44]));
+        test_chip8
+            .execute_opcode((Opcode::ADD_VV, [0x88, 0x44]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0x4a);
         assert_eq!(test_chip8.v_register[0xf], 0x01);
     }
@@ -1056,7 +2167,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xaa;
         test_chip8.v_register[4] = 0x0a;
-        test_chip8.execute_opcode((Opcode::SUB, [0x88, 0x45]));
+        test_chip8
+            .execute_opcode((Opcode::SUB, [0x88, 0x45]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0xa0);
         assert_eq!(test_chip8.v_register[0xf], 0x01);
     }
@@ -1066,7 +2179,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0x0a;
         test_chip8.v_register[4] = 0xaa;
-        test_chip8.execute_opcode((Opcode::SUB, [0x88, 0x45]));
+        test_chip8
+            .execute_opcode((Opcode::SUB, [0x88, 0x45]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0x60);
         assert_eq!(test_chip8.v_register[0xf], 0x00);
     }
@@ -1081,7 +2196,9 @@ mod tests {
     fn execute_shr_no_overflow() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xee;
-        test_chip8.execute_opcode((Opcode::SHR, [0x88, 0x46]));
+        test_chip8
+            .execute_opcode((Opcode::SHR, [0x88, 0x46]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0x77);
         assert_eq!(test_chip8.v_register[0xf], 0x00);
     }
@@ -1090,7 +2207,9 @@ mod tests {
     fn execute_shr_overflow() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xef;
-        test_chip8.execute_opcode((Opcode::SHR, [0x88, 0x46]));
+        test_chip8
+            .execute_opcode((Opcode::SHR, [0x88, 0x46]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0x77);
         assert_eq!(test_chip8.v_register[0xf], 0x01);
     }
@@ -1106,7 +2225,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0x0a;
         test_chip8.v_register[4] = 0xaa;
-        test_chip8.execute_opcode((Opcode::SUBN, [0x88, 0x47]));
+        test_chip8
+            .execute_opcode((Opcode::SUBN, [0x88, 0x47]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0xa0);
         assert_eq!(test_chip8.v_register[0xf], 0x01);
     }
@@ -1116,7 +2237,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[8] = 0xaa;
         test_chip8.v_register[4] = 0xa0;
-        test_chip8.execute_opcode((Opcode::SUBN, [0x88, 0x47]));
+        test_chip8
+            .execute_opcode((Opcode::SUBN, [0x88, 0x47]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[8], 0xf6);
         assert_eq!(test_chip8.v_register[0xf], 0x00);
     }
@@ -1131,7 +2254,9 @@ mod tests {
     fn execute_shl_overflow() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[4] = 0xaa;
-        test_chip8.execute_opcode((Opcode::SHL, [0x84, 0x3e]));
+        test_chip8
+            .execute_opcode((Opcode::SHL, [0x84, 0x3e]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[4], 0x54);
         assert_eq!(test_chip8.v_register[0xf], 1);
     }
@@ -1140,7 +2265,9 @@ mod tests {
     fn execute_shl_no_overflow() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[4] = 0x1a;
-        test_chip8.execute_opcode((Opcode::SHL, [0x84, 0x3e]));
+        test_chip8
+            .execute_opcode((Opcode::SHL, [0x84, 0x3e]))
+            .unwrap();
         assert_eq!(test_chip8.v_register[4], 0x34);
         assert_eq!(test_chip8.v_register[0xf], 0);
     }
@@ -1152,20 +2279,24 @@ mod tests {
     }
 
     #[test]
-    fn execute_sne_VV_not_equal() {
+    fn execute_sne_vv_not_equal() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[4] = 0xaa;
         test_chip8.v_register[3] = 0xba;
-        test_chip8.execute_opcode((Opcode::SNE_VV, [0x94, 0x30]));
+        test_chip8
+            .execute_opcode((Opcode::SNE_VV, [0x94, 0x30]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x204);
     }
 
     #[test]
-    fn execute_sne_VV_equal() {
+    fn execute_sne_vv_equal() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[4] = 0xaa;
         test_chip8.v_register[3] = 0xaa;
-        test_chip8.execute_opcode((Opcode::SNE_VV, [0x94, 0x30]));
+        test_chip8
+            .execute_opcode((Opcode::SNE_VV, [0x94, 0x30]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x202);
     }
 
@@ -1178,7 +2309,9 @@ mod tests {
     #[test]
     fn execute_ld_ia() {
         let mut test_chip8 = Chip8::init();
-        test_chip8.execute_opcode((Opcode::LD_IA, [0xA4, 0x30]));
+        test_chip8
+            .execute_opcode((Opcode::LD_IA, [0xA4, 0x30]))
+            .unwrap();
         assert_eq!(test_chip8.I, 0x430);
     }
 
@@ -1192,7 +2325,9 @@ mod tests {
     fn execute_jp_va() {
         let mut test_chip8 = Chip8::init();
         test_chip8.v_register[0] = 0xa;
-        test_chip8.execute_opcode((Opcode::JP_VA, [0xb4, 0x30]));
+        test_chip8
+            .execute_opcode((Opcode::JP_VA, [0xb4, 0x30]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x43a);
     }
 
@@ -1202,6 +2337,21 @@ mod tests {
         assert_eq!(decoded.unwrap(), (Opcode::RND, [0xccu8, 0xccu8]));
     }
 
+    #[test]
+    fn execute_rnd_repeatable() {
+        let mut first = Chip8::init();
+        let mut second = Chip8::init();
+        first.set_random_seed(42);
+        second.set_random_seed(first.random_seed());
+        for _ in 0..16 {
+            first.execute_opcode((Opcode::RND, [0xc3, 0x0f])).unwrap();
+            second.execute_opcode((Opcode::RND, [0xc3, 0x0f])).unwrap();
+            assert_eq!(first.v_register[3], second.v_register[3]);
+            assert_eq!(first.v_register[3] & 0xf0, 0);
+        }
+        assert_eq!(first.pc, 0x200 + 16 * 2);
+    }
+
     #[test]
     fn decode_drw() {
         let decoded = decode_opcode([0xdd, 0xdd]);
@@ -1219,7 +2369,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.key[0xe] = true;
         test_chip8.v_register[0xe] = 0xe;
-        test_chip8.execute_opcode((Opcode::SKP, [0xee, 0x9e]));
+        test_chip8
+            .execute_opcode((Opcode::SKP, [0xee, 0x9e]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x204);
     }
 
@@ -1228,10 +2380,27 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.key[0xe] = false;
         test_chip8.v_register[0x4] = 0xe;
-        test_chip8.execute_opcode((Opcode::SKP, [0xee, 0x9e]));
+        test_chip8
+            .execute_opcode((Opcode::SKP, [0xee, 0x9e]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x202);
     }
 
+    #[test]
+    fn execute_skp_uses_the_low_nibble() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.key[0x2] = true;
+        test_chip8.v_register[0xe] = 0x32;
+        test_chip8
+            .execute_opcode((Opcode::SKP, [0xee, 0x9e]))
+            .unwrap();
+        assert_eq!(test_chip8.pc, 0x204);
+        test_chip8
+            .execute_opcode((Opcode::SKNP, [0xee, 0xa1]))
+            .unwrap();
+        assert_eq!(test_chip8.pc, 0x206);
+    }
+
     #[test]
     fn decode_sknp() {
         let decoded = decode_opcode([0xe4, 0xa1]);
@@ -1243,7 +2412,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.key[0x4] = true;
         test_chip8.v_register[4] = 4;
-        test_chip8.execute_opcode((Opcode::SKNP, [0xe4, 0xa1]));
+        test_chip8
+            .execute_opcode((Opcode::SKNP, [0xe4, 0xa1]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x202);
     }
 
@@ -1252,7 +2423,9 @@ mod tests {
         let mut test_chip8 = Chip8::init();
         test_chip8.key[0x4] = false;
         test_chip8.v_register[4] = 4;
-        test_chip8.execute_opcode((Opcode::SKNP, [0xe4, 0xa1]));
+        test_chip8
+            .execute_opcode((Opcode::SKNP, [0xe4, 0xa1]))
+            .unwrap();
         assert_eq!(test_chip8.pc, 0x204);
     }
 
@@ -1274,6 +2447,18 @@ mod tests {
         assert_eq!(decoded.unwrap(), (Opcode::LD_DTV, [0xfau8, 0x15u8]));
     }
 
+    #[test]
+    fn execute_ld_dtv() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.v_register[0xa] = 0x3c;
+        test_chip8
+            .execute_opcode((Opcode::LD_DTV, [0xfa, 0x15]))
+            .unwrap();
+        assert_eq!(test_chip8.delay_timer, 0x3c);
+        assert_eq!(test_chip8.v_register[0xa], 0x3c);
+        assert_eq!(test_chip8.pc, 0x202);
+    }
+
     #[test]
     fn decode_ld_stv() {
         let decoded = decode_opcode([0xfe, 0x18]);
@@ -1292,6 +2477,16 @@ mod tests {
         assert_eq!(decoded.unwrap(), (Opcode::LD_FV, [0xf3u8, 0x29u8]));
     }
 
+    #[test]
+    fn execute_ld_fv_uses_the_low_nibble() {
+        let mut test_chip8 = Chip8::init();
+        test_chip8.v_register[3] = 0xfa;
+        test_chip8
+            .execute_opcode((Opcode::LD_FV, [0xf3, 0x29]))
+            .unwrap();
+        assert_eq!(test_chip8.I, 0x050 + 0xa * 5);
+    }
+
     #[test]
     fn decode_ld_bv() {
         let decoded = decode_opcode([0xfe, 0x33]);
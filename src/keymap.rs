@@ -0,0 +1,309 @@
+use std::fs;
+use std::path::Path;
+
+// which host keys press which chip8 keys and run which emulator actions. keymap
+// files have one binding per line, the chip8 key or action, an equals sign and
+// the host keys separated by commas, # starts a comment:
+//
+//   5 = W, Up
+//   pause = P
+//   a = scancode:Z
+//
+// key names are the ones sdl uses. a plain name is the key that prints it, so it
+// moves with the keyboard layout, scancode:NAME is the key in that place on a us
+// keyboard whatever it prints. the keypad is bound by scancode out of the box so
+// the grid stays in the same spot on azerty and qwertz keyboards
+
+// the qwerty layout the emulator always had, by place on the keyboard
+//  1 2 3 4      1 2 3 C
+//  Q W E R  ->  4 5 6 D
+//  A S D F      7 8 9 E
+//  Z X C V      A 0 B F
+pub const DEFAULT_KEYMAP: &str = "\
+1 = scancode:1
+2 = scancode:2
+3 = scancode:3
+c = scancode:4
+4 = scancode:Q
+5 = scancode:W
+6 = scancode:E
+d = scancode:R
+7 = scancode:A
+8 = scancode:S
+9 = scancode:D
+e = scancode:F
+a = scancode:Z
+0 = scancode:X
+b = scancode:C
+f = scancode:V
+pause = P
+reset = F10
+quit = Escape
+record = F12
+rewind = Backspace
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pause,
+    // restarts the rom from the beginning
+    Reset,
+    Quit,
+    // starts and stops recording a movie
+    Record,
+    // plays backwards while held
+    Rewind,
+}
+
+impl Action {
+    pub const ALL: [Action; 5] = [
+        Action::Pause,
+        Action::Reset,
+        Action::Quit,
+        Action::Record,
+        Action::Rewind,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Pause => "pause",
+            Action::Reset => "reset",
+            Action::Quit => "quit",
+            Action::Record => "record",
+            Action::Rewind => "rewind",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Key(u8),
+    Action(Action),
+}
+
+// a host key by name, the frontend turns it into whatever its input library uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostKey {
+    Keycode(String),
+    Scancode(String),
+}
+
+impl HostKey {
+    fn parse(text: &str) -> HostKey {
+        match text.split_once(':') {
+            Some((prefix, name)) if prefix.eq_ignore_ascii_case("scancode") => {
+                HostKey::Scancode(name.trim().to_string())
+            }
+            _ => HostKey::Keycode(text.to_string()),
+        }
+    }
+
+    // names are compared without case, the same as sdl looks them up. a keycode is
+    // the same key as a scancode when the key in that place prints it, which
+    // printed tells for the keyboard layout in use
+    fn same_as<F>(&self, other: &HostKey, printed: &F) -> bool
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        match (self, other) {
+            (HostKey::Keycode(a), HostKey::Keycode(b))
+            | (HostKey::Scancode(a), HostKey::Scancode(b)) => a.eq_ignore_ascii_case(b),
+            (HostKey::Keycode(keycode), HostKey::Scancode(scancode))
+            | (HostKey::Scancode(scancode), HostKey::Keycode(keycode)) => {
+                printed(scancode).is_some_and(|name| name.eq_ignore_ascii_case(keycode))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(HostKey, Binding)>,
+}
+
+impl Keymap {
+    pub fn parse(text: &str) -> Result<Keymap, String> {
+        let mut keymap = Keymap {
+            bindings: Vec::new(),
+        };
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", line_number + 1, message);
+            let (binding, keys) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `<key or action> = <host keys>`"))?;
+            let binding = binding.trim();
+            let binding = match Action::from_name(binding) {
+                Some(action) => Binding::Action(action),
+                None => u8::from_str_radix(binding, 16)
+                    .ok()
+                    .filter(|key| *key < 16 && binding.len() == 1)
+                    .map(Binding::Key)
+                    .ok_or_else(|| error(&format!("unknown chip8 key or action {}", binding)))?,
+            };
+            for key in keys.split(',').map(str::trim) {
+                if key.is_empty() {
+                    return Err(error("empty host key name"));
+                }
+                keymap.bindings.push((HostKey::parse(key), binding));
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Keymap, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+        Keymap::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    // the arrow keys, space and left shift on the directions and buttons the rom
+    // database lists for a rom, see RomInfo::keys
+    pub fn from_rom_keys(keys: &[(String, u8)]) -> Keymap {
+        let bindings = keys
+            .iter()
+            .filter_map(|(name, key)| {
+                let host = match name.as_str() {
+                    "up" => "Up",
+                    "down" => "Down",
+                    "left" => "Left",
+                    "right" => "Right",
+                    "a" => "Space",
+                    "b" => "Left Shift",
+                    _ => return None,
+                };
+                Some((HostKey::Keycode(host.to_string()), Binding::Key(*key)))
+            })
+            .collect();
+        Keymap { bindings }
+    }
+
+    pub fn bindings(&self) -> &[(HostKey, Binding)] {
+        &self.bindings
+    }
+
+    // lays other over this one. every chip8 key or action it mentions loses the
+    // host keys it had here, and every host key it uses is taken from whatever
+    // it was bound to, so a file only has to list what it changes. this assumes a
+    // us layout, where every key prints the name of its scancode
+    pub fn merge(&mut self, other: &Keymap) {
+        self.merge_with_layout(other, |scancode| Some(scancode.to_string()));
+    }
+
+    // merge for another keyboard layout, printed gives the keycode name the key
+    // with a scancode name prints
+    pub fn merge_with_layout<F>(&mut self, other: &Keymap, printed: F)
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        self.bindings.retain(|(host, binding)| {
+            other.bindings.iter().all(|(other_host, other_binding)| {
+                other_binding != binding && !other_host.same_as(host, &printed)
+            })
+        });
+        self.bindings.extend(other.bindings.iter().cloned());
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::parse(DEFAULT_KEYMAP).expect("the default keymap is broken")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(keymap: &Keymap, binding: Binding) -> Vec<HostKey> {
+        keymap
+            .bindings()
+            .iter()
+            .filter(|(_, bound)| *bound == binding)
+            .map(|(host, _)| host.clone())
+            .collect()
+    }
+
+    #[test]
+    fn default_keymap_is_the_qwerty_grid() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.bindings().len(), 21);
+        assert_eq!(
+            hosts(&keymap, Binding::Key(0xc)),
+            [HostKey::Scancode("4".to_string())]
+        );
+        assert_eq!(
+            hosts(&keymap, Binding::Action(Action::Quit)),
+            [HostKey::Keycode("Escape".to_string())]
+        );
+    }
+
+    #[test]
+    fn files_override_what_they_mention() {
+        let mut keymap = Keymap::default();
+        let file = Keymap::parse("# azerty\n5 = Z, Up\nQUIT = q\n pause = scancode: P \n").unwrap();
+        keymap.merge(&file);
+        assert_eq!(
+            hosts(&keymap, Binding::Key(5)),
+            [
+                HostKey::Keycode("Z".to_string()),
+                HostKey::Keycode("Up".to_string())
+            ]
+        );
+        assert_eq!(
+            hosts(&keymap, Binding::Action(Action::Pause)),
+            [HostKey::Scancode("P".to_string())]
+        );
+        // escape no longer quits, q does
+        assert_eq!(
+            hosts(&keymap, Binding::Action(Action::Quit)),
+            [HostKey::Keycode("q".to_string())]
+        );
+        // q is the key in the place of scancode:Q, which now quits instead of pressing 4
+        assert!(hosts(&keymap, Binding::Key(4)).is_empty());
+        assert_eq!(hosts(&keymap, Binding::Key(6)).len(), 1);
+        // Z prints z on qwerty, so scancode:Z no longer presses A as well
+        assert!(hosts(&keymap, Binding::Key(0xa)).is_empty());
+    }
+
+    #[test]
+    fn keycodes_match_scancodes_through_the_layout() {
+        // on azerty the key in the place of a us Z prints W
+        let azerty = |scancode: &str| {
+            Some(match scancode {
+                "Z" => "W".to_string(),
+                "W" => "Z".to_string(),
+                other => other.to_string(),
+            })
+        };
+        let mut keymap = Keymap::default();
+        keymap.merge_with_layout(&Keymap::parse("7 = W\n").unwrap(), azerty);
+        assert!(hosts(&keymap, Binding::Key(0xa)).is_empty());
+        assert_eq!(
+            hosts(&keymap, Binding::Key(5)),
+            [HostKey::Scancode("W".to_string())]
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        assert_eq!(
+            Keymap::parse("1 = X\n10 = Y\n"),
+            Err("line 2: unknown chip8 key or action 10".to_string())
+        );
+        assert!(Keymap::parse("fly = F\n").is_err());
+        assert!(Keymap::parse("5 W\n").is_err());
+        assert!(Keymap::parse("5 = W,\n").is_err());
+    }
+}
@@ -0,0 +1,38 @@
+//! Chip8 emulator core. This crate has no frontend dependencies so it can be
+//! driven by the SDL player in `main.rs`, tests or any other tool.
+
+pub mod assembler;
+pub mod audio;
+pub mod database;
+pub mod debugger;
+pub mod disassembler;
+pub mod display;
+mod error;
+pub mod gdb;
+pub mod hash;
+pub mod input;
+mod interperter;
+mod json;
+pub mod keymap;
+pub mod movie;
+pub mod octo;
+pub mod platform;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod scheduler;
+pub mod settings;
+mod state;
+pub mod timing;
+
+pub use crate::audio::AudioBackend;
+pub use crate::display::DisplayBackend;
+pub use crate::error::Chip8Error;
+pub use crate::input::{KeyEvent, KeypadInput};
+pub use crate::interperter::{
+    decode_opcode, Chip8, Opcode, HIRES_HEIGHT, HIRES_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+pub use crate::platform::Platform;
+pub use crate::quirks::Quirks;
+pub use crate::random::RandomAlgorithm;
+pub use crate::state::SAVE_STATE_VERSION;
@@ -1,85 +1,429 @@
-mod interperter;
+mod options;
 mod render;
+mod sdl_audio;
+mod sdl_input;
 
-use crate::interperter::*;
-//use crate::render::*;
+use crate::options::{parse_options, Options, USAGE};
+use crate::render::SdlDisplay;
+use crate::sdl_audio::SdlBeeper;
+use crate::sdl_input::SdlKeypad;
+use chip8::audio::NullAudio;
+use chip8::database::RomInfo;
+use chip8::debugger::{Command, CommandReader, Debugger};
+use chip8::display::TerminalDisplay;
+use chip8::keymap::{Action, Keymap, DEFAULT_KEYMAP};
+use chip8::movie::MovieRecorder;
+use chip8::rewind::RewindBuffer;
+use chip8::scheduler::{FrameBudget, Scheduler, Speed};
+use chip8::{AudioBackend, Chip8, Chip8Error, SCREEN_HEIGHT, SCREEN_WIDTH};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-//use std::thread::sleep;
-use std::time::{Duration, Instant};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::{Sdl, VideoSubsystem};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread::sleep;
+use std::time::Instant;
 
 fn main() {
-    let screen_width = 64;
-    let screen_height = 32;
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    if args.iter().any(|arg| arg == "--print-keymap") {
+        print!("{}", DEFAULT_KEYMAP);
+        return;
+    }
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
 
-    // load game
-    let mut chip8 = Chip8::init();
-    &chip8.load_game("games\\snake.ch8".to_string());
+    let chip8 = match load_game(&options) {
+        Ok(chip8) => chip8,
+        Err(error) => {
+            eprintln!("{}: {}", options.rom, error);
+            process::exit(1);
+        }
+    };
+    if let Some(info) = chip8.rom_info() {
+        if info.authors.is_empty() {
+            println!("{}", info.title);
+        } else {
+            println!("{} by {}", info.title, info.authors.join(", "));
+        }
+    }
+    // P pauses and debugger commands are typed into the terminal, see debugger::HELP
+    let mut debugger = Debugger::new();
+    debugger.paused = options.paused;
 
-    //setup window
+    if options.windowless {
+        run_windowless(chip8, debugger, &options);
+        return;
+    }
+    // sdl only knows the keyboard layout the keymap is matched against once video is up
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window(
-            "Chip8",
-            (screen_width as f32 * 10.0) as u32,
-            (screen_height as f32 * 10.0) as u32,
-        )
-        .position_centered()
-        .build()
-        .unwrap();
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
-
-    let mut pixels = render::make_pixels(screen_width, screen_height);
-    render::color_pixels(&[0; 32 * 64], &mut pixels, &mut canvas);
-    let run_speed = 16666666; // normal run speed of the emulator
-    let slow: u32 = 1000000000;
-    let mut speed: u32 = run_speed; // used to speed up or slow down the run time to take a look at the opcode
-
-    'emulator_loop: loop {
-        let time = Instant::now();
-        let mut opcode_count = 0;
-        while (Instant::now() - time) < Duration::from_millis(16) {
-            let next_opcode = &chip8.fetch_opcode();
-            let decoded_opcode = decode_opcode(*next_opcode);
-
-            if decoded_opcode != None {
-                &chip8.execute_opcode(decoded_opcode.unwrap());
+    let keypad =
+        match load_keymap(&options, chip8.rom_info()).and_then(|keymap| SdlKeypad::new(&keymap)) {
+            Ok(keypad) => keypad,
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(2);
             }
+        };
+    run_window(
+        chip8,
+        debugger,
+        &sdl_context,
+        &video_subsystem,
+        keypad,
+        &options,
+    );
+}
+
+// the keycode name the key with a scancode name prints on the current layout
+fn printed_by(scancode: &str) -> Option<String> {
+    Scancode::from_name(scancode)
+        .and_then(Keycode::from_scancode)
+        .map(|keycode| keycode.name())
+}
+
+// the default keys, then the ones the rom database lists, the global keymap file,
+// the one next to the rom and --keymap, each laid over the ones before
+fn load_keymap(options: &Options, info: Option<&RomInfo>) -> Result<Keymap, String> {
+    let mut keymap = Keymap::default();
+    if let Some(info) = info {
+        keymap.merge_with_layout(&Keymap::from_rom_keys(&info.keys), printed_by);
+    }
+    // snake.ch8 uses snake.ch8.keymap when there is one
+    let files = [
+        global_keymap_path(),
+        Some(PathBuf::from(format!("{}.keymap", options.rom))),
+    ];
+    for path in files.iter().flatten().filter(|path| path.exists()) {
+        keymap.merge_with_layout(&Keymap::load(path)?, printed_by);
+    }
+    if let Some(path) = &options.keymap {
+        keymap.merge_with_layout(&Keymap::load(path)?, printed_by);
+    }
+    Ok(keymap)
+}
+
+// $XDG_CONFIG_HOME/chip8/keymap, %APPDATA%\chip8\keymap or ~/.config/chip8/keymap
+fn global_keymap_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("chip8").join("keymap"))
+}
+
+// the rom with the database's settings, then the ones given on the command line
+fn load_game(options: &Options) -> Result<Chip8, Chip8Error> {
+    let mut chip8 = Chip8::init();
+    options.settings.load_game(&mut chip8, &options.rom)?;
+    Ok(chip8)
+}
+
+fn run_window(
+    mut chip8: Chip8,
+    mut debugger: Debugger,
+    sdl_context: &Sdl,
+    video_subsystem: &VideoSubsystem,
+    keypad: SdlKeypad,
+    options: &Options,
+) {
+    let rom_path = options.rom.as_str();
+    let info = chip8.rom_info().cloned();
+    let title = info.as_ref().map_or("Chip8", |info| info.title.as_str());
+    let mut window = video_subsystem.window(
+        title,
+        SCREEN_WIDTH as u32 * options.scale,
+        SCREEN_HEIGHT as u32 * options.scale,
+    );
+    window.position_centered();
+    if options.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().unwrap();
+    // no vsync, the scheduler paces frames and a 144 Hz monitor must not speed up games
+    let canvas = window.into_canvas().build().unwrap();
+    let mut display = SdlDisplay::new(canvas, options.settings.palette_for(info.as_ref()));
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let mut beeper = SdlBeeper::new(&audio_subsystem, options.tone).unwrap();
 
-            let mut event_pump = sdl_context.event_pump().unwrap();
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. } => break 'emulator_loop,
-                    Event::KeyDown { keycode: x, .. } => match x {
-                        Some(Keycode::P) => {
-                            if speed == slow {
-                                speed = run_speed;
-                            } else {
-                                speed = slow
+    let mut commands = CommandReader::stdin();
+    // record restarts the rom and records the keys into a movie until it is pressed again
+    let mut keypad = MovieRecorder::new(keypad);
+    // holding rewind plays the last few minutes backwards
+    let mut rewind = RewindBuffer::new(64 * 1024 * 1024);
+    let mut rewinding = false;
+
+    // the scheduler turns wall clock time into 60 Hz ticks, each one a frame of
+    // instructions and a timer decrement, no matter how fast the screen refreshes
+    let mut scheduler = Scheduler::new(options.settings.speed_for(info.as_ref()));
+    let mut last_time = Instant::now();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    'emulator_loop: loop {
+        for event in event_pump.poll_iter() {
+            for (action, pressed) in keypad.inner.handle_event(&event) {
+                match action {
+                    Action::Quit if pressed => break 'emulator_loop,
+                    Action::Pause if pressed => {
+                        let command = if debugger.paused {
+                            Command::Continue
+                        } else {
+                            Command::Pause
+                        };
+                        println!("{}", debugger.execute(command, &chip8));
+                    }
+                    Action::Reset if pressed => {
+                        if keypad.is_recording() {
+                            save_movie(&mut keypad, &chip8, rom_path);
+                        }
+                        chip8 = match load_game(options) {
+                            Ok(chip8) => chip8,
+                            Err(error) => {
+                                eprintln!("{}", error);
+                                break 'emulator_loop;
                             }
+                        };
+                        rewind.clear();
+                        println!("restarted {}", rom_path);
+                        continue 'emulator_loop;
+                    }
+                    Action::Record if pressed => {
+                        // a movie replays a whole number of instructions each frame, so
+                        // --ips rounded to one would drift from what was played
+                        if keypad.is_recording() {
+                            save_movie(&mut keypad, &chip8, rom_path);
+                        } else if let Speed::InstructionsPerFrame(instructions) = scheduler.speed()
+                        {
+                            chip8 = match load_game(options) {
+                                Ok(chip8) => chip8,
+                                Err(error) => {
+                                    eprintln!("{}", error);
+                                    break 'emulator_loop;
+                                }
+                            };
+                            rewind.clear();
+                            keypad.start(&chip8, instructions);
+                            println!("recording a movie, press record again to stop");
+                            continue 'emulator_loop;
+                        } else {
+                            println!(
+                                "movies need --speed, a fixed number of instructions per frame"
+                            );
                         }
-                        Some(Keycode::Escape) => break 'emulator_loop,
-                        _ => chip8.check_key_state(event),
-                    },
-
-                    Event::KeyUp { keycode: x, .. } => {
-                        chip8.check_key_state(event);
                     }
+                    // a movie can only be replayed if the session never jumped around
+                    Action::Rewind => rewinding = pressed && !keypad.is_recording(),
                     _ => {}
                 }
             }
-            opcode_count += 1;
-            if opcode_count == 9 {
-                break;
+            match event {
+                Event::Quit { .. } => break 'emulator_loop,
+                // F1-F9 load a save state slot, with shift held they save to it
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(slot) = state_slot(keycode).filter(|_| !keypad.is_recording()) {
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            save_state(&chip8, rom_path, slot);
+                        } else {
+                            load_state(&mut chip8, rom_path, slot);
+                        }
+                    }
+                }
+                _ => {}
             }
         }
 
-        &canvas.clear();
-        render::color_pixels(&chip8.gfx, &mut pixels, &mut canvas);
-        canvas.present();
+        run_commands(&mut commands, &mut debugger, &chip8);
+
+        let now = Instant::now();
+        let ticks = scheduler.advance(now - last_time);
+        last_time = now;
+        for _ in 0..ticks {
+            if rewinding {
+                if let Some(state) = rewind.pop() {
+                    if let Err(error) = chip8.load_state(&state) {
+                        // the rest of the buffer is no better, stop and stay where we are
+                        eprintln!("could not rewind: {}", error);
+                        rewind.clear();
+                        rewinding = false;
+                    }
+                }
+                continue;
+            }
+
+            // keys are read once at the start of a frame, the same as Chip8::run_frame,
+            // so a recorded movie plays back exactly as the game saw it. a paused tick
+            // is no frame at all and must not be counted in one
+            let running = debugger.is_running();
+            if running {
+                chip8.update_keys(&mut keypad);
+            }
+            run_tick(&mut scheduler, &mut chip8, &mut debugger);
+            if running && !debugger.is_running() && keypad.is_recording() {
+                // a breakpoint or step stopped part way through the frame, which a
+                // movie has no way to replay
+                keypad.stop(&chip8);
+                println!("the debugger stopped in the middle of a frame, dropped the movie");
+            }
+            if !debugger.paused {
+                chip8.decrease_timers();
+                rewind.push(chip8.save_state());
+            }
+        }
 
-        chip8.decrease_timers();
+        if ticks > 0 {
+            chip8.update_display(&mut display);
+            chip8.update_audio(&mut beeper);
+        }
+        sleep(scheduler.time_until_next_tick());
+    }
+
+    if keypad.is_recording() {
+        save_movie(&mut keypad, &chip8, rom_path);
+    }
+}
+
+// draws into the terminal instead of a window. there is no keypad since stdin is
+// taken by the debugger, so this is for watching roms and debugging them over ssh
+fn run_windowless(mut chip8: Chip8, mut debugger: Debugger, options: &Options) {
+    // sound is a bonus here, the terminal may be on a machine without any
+    let audio_subsystem = sdl2::init().and_then(|sdl| sdl.audio()).ok();
+    let mut beeper: Box<dyn AudioBackend> = match audio_subsystem
+        .as_ref()
+        .map(|audio| SdlBeeper::new(audio, options.tone))
+    {
+        Some(Ok(beeper)) => Box::new(beeper),
+        _ => Box::new(NullAudio),
+    };
+    let mut display = TerminalDisplay::stdout();
+    print!("\x1b[2J");
+
+    let mut commands = CommandReader::stdin();
+    let mut scheduler = Scheduler::new(options.settings.speed_for(chip8.rom_info()));
+    let mut last_time = Instant::now();
+    while !chip8.is_halted() {
+        run_commands(&mut commands, &mut debugger, &chip8);
+
+        let now = Instant::now();
+        let ticks = scheduler.advance(now - last_time);
+        last_time = now;
+        for _ in 0..ticks {
+            run_tick(&mut scheduler, &mut chip8, &mut debugger);
+            if !debugger.paused {
+                chip8.decrease_timers();
+            }
+        }
+
+        if ticks > 0 {
+            chip8.update_display(&mut display);
+            chip8.update_audio(beeper.as_mut());
+        }
+        sleep(scheduler.time_until_next_tick());
+    }
+}
+
+fn run_commands(commands: &mut CommandReader, debugger: &mut Debugger, chip8: &Chip8) {
+    for line in commands.poll() {
+        match Command::parse(&line) {
+            Ok(command) => {
+                let output = debugger.execute(command, chip8);
+                if !output.is_empty() {
+                    println!("{}", output);
+                }
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+}
+
+// one tick's worth of instructions or cycles, whichever the scheduler hands out
+fn run_tick(scheduler: &mut Scheduler, chip8: &mut Chip8, debugger: &mut Debugger) {
+    match scheduler.budget_for_tick() {
+        FrameBudget::Instructions(instructions) => {
+            for _ in 0..instructions {
+                debug_step(debugger, chip8);
+            }
+        }
+        FrameBudget::Cycles(cycles) => {
+            chip8.add_cycles(cycles);
+            while chip8.has_cycles_left() && debugger.is_running() && !chip8.is_halted() {
+                debug_step(debugger, chip8);
+            }
+        }
+    }
+}
+
+// one instruction through the debugger, which holds it back while paused
+fn debug_step(debugger: &mut Debugger, chip8: &mut Chip8) {
+    match debugger.step(chip8) {
+        Ok(Some(message)) => println!("{}", message),
+        Ok(None) => {}
+        // stay open so the machine can be looked at
+        Err(error) => eprintln!("{}, paused", chip8.describe_error(&error)),
+    }
+}
+
+fn state_slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+fn state_path(rom_path: &str, slot: u8) -> String {
+    // slots live next to the rom, snake.ch8 slot 1 is snake.ch8.state1
+    format!("{}.state{}", rom_path, slot)
+}
+
+fn save_state(chip8: &Chip8, rom_path: &str, slot: u8) {
+    let path = state_path(rom_path, slot);
+    match fs::write(&path, chip8.save_state()) {
+        Ok(()) => println!("saved state to {}", path),
+        Err(error) => eprintln!("could not save state to {}: {}", path, error),
+    }
+}
+
+fn load_state(chip8: &mut Chip8, rom_path: &str, slot: u8) {
+    let path = state_path(rom_path, slot);
+    match fs::read(&path) {
+        Ok(state) => match chip8.load_state(&state) {
+            Ok(()) => println!("loaded state from {}", path),
+            Err(error) => eprintln!("could not load state from {}: {}", path, error),
+        },
+        Err(error) => eprintln!("could not read {}: {}", path, error),
+    }
+}
+
+fn save_movie(keypad: &mut MovieRecorder<SdlKeypad>, chip8: &Chip8, rom_path: &str) {
+    if let Some(movie) = keypad.stop(chip8) {
+        let path = format!("{}.movie", rom_path);
+        match fs::write(&path, movie.to_text()) {
+            Ok(()) => println!("saved movie to {}", path),
+            Err(error) => eprintln!("could not save movie to {}: {}", path, error),
+        }
     }
 }
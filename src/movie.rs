@@ -0,0 +1,275 @@
+use crate::error::Chip8Error;
+use crate::hash::to_hex;
+use crate::input::{format_key_event, parse_key_events, KeyEvent, KeypadInput, ScriptedKeypad};
+use crate::interperter::Chip8;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::random::RandomAlgorithm;
+
+// movie files are plain text so they can be attached to bug reports and read by hand:
+//   chip8-movie 1
+//   rom <sha-1 of the rom>
+//   platform <chip8|schip|xochip>
+//   quirks <quirk bits in hex>
+//   random xorshift <seed>
+//   speed <instructions per frame>
+//   frames <frames played>
+//   final <sha-1 of the framebuffer after the last frame>    (optional)
+//   events
+//   <frame> <down|up> <hex key>                               (one per key change)
+// bump the version whenever this changes
+const HEADER: &str = "chip8-movie";
+pub const MOVIE_VERSION: u32 = 1;
+
+// everything needed to replay a session exactly, from power on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_hash: [u8; 20],
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub random_algorithm: RandomAlgorithm,
+    pub random_seed: u64,
+    pub instructions_per_frame: u32,
+    pub frames: u64,
+    pub final_frame: Option<[u8; 20]>,
+    pub events: Vec<(u64, KeyEvent)>,
+}
+
+fn parse_hash(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 || !text.is_ascii() {
+        return None;
+    }
+    let mut hash = [0u8; 20];
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+impl Movie {
+    pub fn parse(text: &str) -> Result<Movie, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line.trim() == format!("{} {}", HEADER, MOVIE_VERSION) => {}
+            Some((_, line)) if line.starts_with(HEADER) => {
+                return Err(format!(
+                    "line 1: movie version is not supported, expected {}",
+                    MOVIE_VERSION
+                ))
+            }
+            _ => return Err("line 1: not a chip8 movie".to_string()),
+        }
+
+        let mut rom_hash = None;
+        let mut platform = None;
+        let mut quirks = None;
+        let mut random = None;
+        let mut instructions_per_frame = None;
+        let mut frames = None;
+        let mut final_frame = None;
+        let mut events = None;
+        for (line_number, line) in &mut lines {
+            let bad_line = || format!("line {}: could not read `{}`", line_number + 1, line);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                [] => {}
+                ["rom", hash] => rom_hash = Some(parse_hash(hash).ok_or_else(bad_line)?),
+                ["platform", name] => {
+                    platform = Some(Platform::from_name(name).ok_or_else(bad_line)?)
+                }
+                ["quirks", bits] => {
+                    let bits = u8::from_str_radix(bits, 16).map_err(|_| bad_line())?;
+                    quirks = Some(Quirks::from_bits(bits));
+                }
+                ["random", algorithm, seed] => {
+                    let algorithm = match *algorithm {
+                        "xorshift" => RandomAlgorithm::Xorshift,
+                        _ => return Err(bad_line()),
+                    };
+                    random = Some((algorithm, seed.parse().map_err(|_| bad_line())?));
+                }
+                ["speed", speed] => {
+                    instructions_per_frame = Some(speed.parse().map_err(|_| bad_line())?)
+                }
+                ["frames", count] => frames = Some(count.parse().map_err(|_| bad_line())?),
+                ["final", hash] => final_frame = Some(parse_hash(hash).ok_or_else(bad_line)?),
+                ["events"] => {
+                    let rest: Vec<&str> = lines.map(|(_, line)| line).collect();
+                    events = Some(parse_key_events(&rest.join("\n"), line_number + 2)?);
+                    break;
+                }
+                _ => return Err(bad_line()),
+            }
+        }
+
+        let missing = |name: &str| format!("movie has no {} line", name);
+        let (random_algorithm, random_seed) = random.ok_or_else(|| missing("random"))?;
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            platform: platform.ok_or_else(|| missing("platform"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            random_algorithm,
+            random_seed,
+            instructions_per_frame: instructions_per_frame.ok_or_else(|| missing("speed"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            final_frame,
+            events: events.ok_or_else(|| missing("events"))?,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}\n", HEADER, MOVIE_VERSION);
+        text += &format!("rom {}\n", to_hex(&self.rom_hash));
+        text += &format!("platform {}\n", self.platform.name());
+        text += &format!("quirks {:02x}\n", self.quirks.to_bits());
+        let algorithm = match self.random_algorithm {
+            RandomAlgorithm::Xorshift => "xorshift",
+        };
+        text += &format!("random {} {}\n", algorithm, self.random_seed);
+        text += &format!("speed {}\n", self.instructions_per_frame);
+        text += &format!("frames {}\n", self.frames);
+        if let Some(hash) = self.final_frame {
+            text += &format!("final {}\n", to_hex(&hash));
+        }
+        text += "events\n";
+        for (frame, event) in &self.events {
+            text += &format_key_event(*frame, *event);
+            text.push('\n');
+        }
+        text
+    }
+
+    // replays the movie as fast as possible on a chip8 that has just loaded the rom
+    // and returns the hash of the final framebuffer, compare it with final_frame
+    pub fn play(&self, chip8: &mut Chip8) -> Result<[u8; 20], Chip8Error> {
+        if chip8.rom_hash() != self.rom_hash {
+            return Err(Chip8Error::MovieRomMismatch);
+        }
+        chip8.set_platform(self.platform);
+        chip8.quirks = self.quirks;
+        chip8.set_random(self.random_algorithm, self.random_seed);
+        let mut keypad = ScriptedKeypad::new(self.events.clone());
+        for _ in 0..self.frames {
+            chip8.run_frame(&mut keypad, self.instructions_per_frame)?;
+        }
+        Ok(chip8.framebuffer_hash())
+    }
+}
+
+// passes key changes through from another keypad, and while recording notes each
+// one down with the frame it happened on. poll must be called exactly once a frame
+pub struct MovieRecorder<K: KeypadInput> {
+    pub inner: K,
+    movie: Option<Movie>,
+}
+
+impl<K: KeypadInput> MovieRecorder<K> {
+    pub fn new(inner: K) -> MovieRecorder<K> {
+        MovieRecorder { inner, movie: None }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.movie.is_some()
+    }
+
+    // call right after the chip8 has been reset and loaded the rom
+    pub fn start(&mut self, chip8: &Chip8, instructions_per_frame: u32) {
+        self.movie = Some(Movie {
+            rom_hash: chip8.rom_hash(),
+            platform: chip8.platform(),
+            quirks: chip8.quirks,
+            random_algorithm: chip8.random_algorithm(),
+            random_seed: chip8.random_seed(),
+            instructions_per_frame,
+            frames: 0,
+            final_frame: None,
+            events: Vec::new(),
+        });
+    }
+
+    pub fn stop(&mut self, chip8: &Chip8) -> Option<Movie> {
+        let mut movie = self.movie.take()?;
+        movie.final_frame = Some(chip8.framebuffer_hash());
+        Some(movie)
+    }
+}
+
+impl<K: KeypadInput> KeypadInput for MovieRecorder<K> {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        let events = self.inner.poll();
+        if let Some(movie) = &mut self.movie {
+            let frame = movie.frames;
+            movie
+                .events
+                .extend(events.iter().map(|event| (frame, *event)));
+            movie.frames += 1;
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // waits for a key with LD_VK, draws that digit and loops back
+    const ROM: [u8; 12] = [
+        0xf0, 0x0a, 0x00, 0xe0, 0xf0, 0x29, 0xd1, 0x25, 0x71, 0x05, 0x12, 0x00,
+    ];
+
+    fn record_session() -> (Movie, [u8; 20]) {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&ROM).unwrap();
+        chip8.set_random_seed(7);
+        let script = ScriptedKeypad::parse("3 down 5\n5 up 5\n9 down a\n12 up a\n").unwrap();
+        let mut recorder = MovieRecorder::new(script);
+        recorder.start(&chip8, 10);
+        for _ in 0..20 {
+            chip8.run_frame(&mut recorder, 10).unwrap();
+        }
+        let hash = chip8.framebuffer_hash();
+        (recorder.stop(&chip8).unwrap(), hash)
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let (movie, _) = record_session();
+        assert_eq!(movie.events.len(), 4);
+        assert_eq!(movie.frames, 20);
+        assert_eq!(Movie::parse(&movie.to_text()).unwrap(), movie);
+    }
+
+    #[test]
+    fn playback_matches_recording() {
+        let (movie, hash) = record_session();
+        assert_eq!(movie.final_frame, Some(hash));
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&ROM).unwrap();
+        assert_eq!(movie.play(&mut chip8).unwrap(), hash);
+    }
+
+    #[test]
+    fn playback_other_rom() {
+        let (movie, _) = record_session();
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(matches!(
+            movie.play(&mut chip8),
+            Err(Chip8Error::MovieRomMismatch)
+        ));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Movie::parse("not a movie").is_err());
+        assert!(Movie::parse("chip8-movie 99\n").is_err());
+        let (movie, _) = record_session();
+        let text = movie.to_text().replace("speed 10", "speed fast");
+        assert_eq!(
+            Movie::parse(&text).unwrap_err(),
+            "line 6: could not read `speed fast`"
+        );
+        let text = movie.to_text() + "30 sideways 1\n";
+        assert!(Movie::parse(&text).unwrap_err().starts_with("line 14:"));
+    }
+}
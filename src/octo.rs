@@ -0,0 +1,1052 @@
+use crate::assembler::AsmError;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// compiles octo source (https://github.com/JohnEarnest/Octo) into a rom. this covers
+// the parts of the language homebrew actually leans on: labels, register and index
+// assignments, if/then, if/begin/else/end, loop/again with while, :alias, :const,
+// :calc, :macro, :byte, :org, :next, :unpack and bare numbers as sprite data.
+// like octo the rom starts with a jump to `: main` and :calc reads right to left
+// without precedence, so use parentheses
+
+const ORIGIN: usize = 0x200;
+
+// which source line every emitted instruction or run of data came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    lines: BTreeMap<u16, usize>,
+}
+
+impl SourceMap {
+    // the line that emitted the byte at address
+    pub fn line(&self, address: u16) -> Option<usize> {
+        self.lines
+            .range(..=address)
+            .next_back()
+            .map(|(_, line)| *line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub rom: Vec<u8>,
+    pub source_map: SourceMap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Patch {
+    // the low 12 bits of the word at the address
+    Address,
+    // the whole word, i := long
+    Long,
+    // v0 := nibble and the high bits, v1 := the low byte
+    Unpack(u8),
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+enum Branch {
+    // the jump past the if body, filled in by else or end
+    If(usize),
+    // the jump past the else body, filled in by end
+    Else(usize),
+}
+
+struct Loop {
+    start: usize,
+    // the jumps out of the loop from while, filled in by again
+    exits: Vec<usize>,
+}
+
+#[derive(Clone, Copy)]
+enum Condition {
+    Equal(u8, Operand),
+    NotEqual(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+    // true when vf ends up equal to the value after the comparison code
+    Flag(u8),
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|character: char| character.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('v').or(text.strip_prefix('V'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        tokens.extend(code.split_whitespace().map(|text| Token {
+            text: text.to_string(),
+            line: index + 1,
+        }));
+    }
+    tokens
+}
+
+const KEYWORDS: &[&str] = &[
+    ":",
+    ":=",
+    "+=",
+    "-=",
+    "=-",
+    "|=",
+    "&=",
+    "^=",
+    ">>=",
+    "<<=",
+    "==",
+    "!=",
+    "<",
+    ">",
+    "<=",
+    ">=",
+    "key",
+    "-key",
+    "i",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "loop",
+    "again",
+    "while",
+    "clear",
+    "return",
+    ";",
+    "exit",
+    "lores",
+    "hires",
+    "bcd",
+    "save",
+    "load",
+    "saveflags",
+    "loadflags",
+    "sprite",
+    "jump",
+    "jump0",
+    "native",
+    "random",
+    "delay",
+    "buzzer",
+    "pitch",
+    "hex",
+    "bighex",
+    "long",
+    "audio",
+    "plane",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "{",
+    "}",
+];
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    file: String,
+    line: usize,
+    // rom[0] is address 0x200
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    patches: Vec<(usize, String, Patch, usize)>,
+    branches: Vec<Branch>,
+    loops: Vec<Loop>,
+    source_map: SourceMap,
+}
+
+impl Compiler {
+    fn error<T>(&self, message: String) -> Result<T, AsmError> {
+        Err(AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            message,
+        })
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of file".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected `{}` but found `{}`", expected, token));
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, address: usize, bytes: &[u8]) -> Result<(), AsmError> {
+        if address < ORIGIN || address + bytes.len() > 0x10000 {
+            return self.error(format!("address {:#x} is outside of the rom", address));
+        }
+        let end = address - ORIGIN + bytes.len();
+        if self.rom.len() < end {
+            self.rom.resize(end, 0);
+        }
+        self.rom[address - ORIGIN..end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), AsmError> {
+        self.source_map.lines.insert(self.here as u16, self.line);
+        self.write(self.here, bytes)?;
+        self.here += bytes.len();
+        Ok(())
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AsmError> {
+        self.emit(&word.to_be_bytes())
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        match self.register_named(&token) {
+            Some(register) => Ok(register),
+            None => self.error(format!("`{}` is not a register", token)),
+        }
+    }
+
+    fn register_named(&self, name: &str) -> Option<u8> {
+        parse_register(name).or_else(|| self.aliases.get(name).copied())
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), AsmError> {
+        if KEYWORDS.contains(&name)
+            || name.starts_with(':')
+            || parse_number(name).is_some()
+            || parse_register(name).is_some()
+        {
+            return self.error(format!("`{}` can not be used as a name", name));
+        }
+        if self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.aliases.contains_key(name)
+            || self.macros.contains_key(name)
+        {
+            return self.error(format!("`{}` is already defined", name));
+        }
+        Ok(())
+    }
+
+    // a number, constant or label that is already known
+    fn known_value(&self, token: &str) -> Option<f64> {
+        parse_number(token)
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|address| *address as f64))
+    }
+
+    fn value(&mut self, bits: u32) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        let value = if token == "{" {
+            self.calc()?
+        } else {
+            match self.known_value(&token) {
+                Some(value) => value,
+                None => return self.error(format!("`{}` is not a number or constant", token)),
+            }
+        };
+        self.fit(value.floor() as i64, bits, &token)
+    }
+
+    fn fit(&self, value: i64, bits: u32, token: &str) -> Result<u16, AsmError> {
+        // bytes may be negative, v0 += -1
+        if bits == 8 && (-128..0).contains(&value) {
+            return Ok((value & 0xff) as u16);
+        }
+        if value < 0 || value >= 1 << bits {
+            return self.error(format!("`{}` does not fit in {} bits", token, bits));
+        }
+        Ok(value as u16)
+    }
+
+    // an address in the low bits of the word emitted at here, labels further down are
+    // filled in once the whole program is read
+    fn address_word(&mut self, high: u16, patch: Patch, bits: u32) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token == "{" {
+            let value = self.calc()?;
+            let address = self.fit(value.floor() as i64, bits, "{ ... }")?;
+            return self.emit_address(high, address, patch);
+        }
+        match self.known_value(&token) {
+            Some(value) => {
+                let address = self.fit(value.floor() as i64, bits, &token)?;
+                self.emit_address(high, address, patch)
+            }
+            None => {
+                self.patches.push((self.here, token, patch, self.line));
+                self.emit_address(high, 0, patch)
+            }
+        }
+    }
+
+    fn emit_address(&mut self, high: u16, address: u16, patch: Patch) -> Result<(), AsmError> {
+        match patch {
+            Patch::Address => self.emit_word(high | address),
+            Patch::Long => {
+                self.emit_word(high)?;
+                self.emit_word(address)
+            }
+            Patch::Unpack(nibble) => {
+                self.emit_word(0x6000 | ((nibble as u16) << 4) | (address >> 8))?;
+                self.emit_word(0x6100 | (address & 0xff))
+            }
+        }
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), AsmError> {
+        self.check_name(&name)?;
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    // the tokens up to the closing brace, the opening one already read
+    fn block(&mut self) -> Result<Vec<Token>, AsmError> {
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = match self.tokens.pop_front() {
+                Some(token) => token,
+                None => return self.error("missing `}`".to_string()),
+            };
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return Ok(body);
+            }
+            body.push(token);
+        }
+    }
+
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        let tokens: Vec<String> = self.block()?.into_iter().map(|token| token.text).collect();
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position)?;
+        if position != tokens.len() {
+            return self.error(format!("unexpected `{}` in expression", tokens[position]));
+        }
+        Ok(value)
+    }
+
+    fn expression(&self, tokens: &[String], position: &mut usize) -> Result<f64, AsmError> {
+        let left = self.term(tokens, position)?;
+        let operator = match tokens.get(*position) {
+            Some(operator) if operator != ")" => operator.as_str(),
+            _ => return Ok(left),
+        };
+        *position += 1;
+        // everything to the right binds first
+        let right = self.expression(tokens, position)?;
+        let (a, b) = (left as i64, right as i64);
+        let truth = |condition: bool| if condition { 1.0 } else { 0.0 };
+        Ok(match operator {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => (a << b) as f64,
+            ">>" => (a >> b) as f64,
+            "<" => truth(left < right),
+            ">" => truth(left > right),
+            "<=" => truth(left <= right),
+            ">=" => truth(left >= right),
+            "==" => truth(left == right),
+            "!=" => truth(left != right),
+            _ => return self.error(format!("unknown operator `{}`", operator)),
+        })
+    }
+
+    fn term(&self, tokens: &[String], position: &mut usize) -> Result<f64, AsmError> {
+        let token = match tokens.get(*position) {
+            Some(token) => token.as_str(),
+            None => return self.error("expression ends early".to_string()),
+        };
+        *position += 1;
+        Ok(match token {
+            "(" => {
+                let value = self.expression(tokens, position)?;
+                if tokens.get(*position).map(String::as_str) != Some(")") {
+                    return self.error("missing `)`".to_string());
+                }
+                *position += 1;
+                value
+            }
+            "-" => -self.term(tokens, position)?,
+            "~" => !(self.term(tokens, position)? as i64) as f64,
+            "!" => {
+                let value = self.term(tokens, position)?;
+                if value == 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            "floor" => self.term(tokens, position)?.floor(),
+            "sin" => self.term(tokens, position)?.sin(),
+            "cos" => self.term(tokens, position)?.cos(),
+            // the byte already emitted at an address
+            "@" => {
+                let address = self.term(tokens, position)? as usize;
+                address
+                    .checked_sub(ORIGIN)
+                    .and_then(|index| self.rom.get(index))
+                    .map_or(0.0, |byte| *byte as f64)
+            }
+            "HERE" => self.here as f64,
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            _ => match self.known_value(token) {
+                Some(value) => value,
+                None => return self.error(format!("`{}` is not defined yet", token)),
+            },
+        })
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        match self.peek().and_then(|token| self.register_named(token)) {
+            Some(register) => {
+                self.next()?;
+                Ok(Operand::Register(register))
+            }
+            None => Ok(Operand::Byte(self.value(8)? as u8)),
+        }
+    }
+
+    // reads a condition and emits any code it needs before the skip
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let left = self.operand()?;
+        let comparison = self.next()?;
+        match (comparison.as_str(), left) {
+            ("key", Operand::Register(x)) => return Ok(Condition::Key(x)),
+            ("-key", Operand::Register(x)) => return Ok(Condition::NotKey(x)),
+            _ => {}
+        }
+        let right = self.operand()?;
+        match (comparison.as_str(), left) {
+            ("==", Operand::Register(x)) => return Ok(Condition::Equal(x, right)),
+            ("!=", Operand::Register(x)) => return Ok(Condition::NotEqual(x, right)),
+            _ => {}
+        }
+        // the rest subtract into vf and test the borrow flag, which is set when the
+        // first value is at least the second
+        let (first, second, flag) = match comparison.as_str() {
+            ">=" => (left, right, 1),
+            "<" => (left, right, 0),
+            "<=" => (right, left, 1),
+            ">" => (right, left, 0),
+            _ => {
+                return self.error(format!(
+                    "`{}` is not a comparison this compiler understands",
+                    comparison
+                ))
+            }
+        };
+        match (first, second) {
+            (Operand::Register(a), Operand::Register(b)) => {
+                self.emit_word(0x8f00 | ((a as u16) << 4))?;
+                self.emit_word(0x8f05 | ((b as u16) << 4))?;
+            }
+            (Operand::Register(a), Operand::Byte(b)) => {
+                self.emit_word(0x6f00 | b as u16)?;
+                self.emit_word(0x8f07 | ((a as u16) << 4))?;
+            }
+            (Operand::Byte(a), Operand::Register(b)) => {
+                self.emit_word(0x6f00 | a as u16)?;
+                self.emit_word(0x8f05 | ((b as u16) << 4))?;
+            }
+            (Operand::Byte(_), Operand::Byte(_)) => {
+                return self.error("a comparison needs at least one register".to_string())
+            }
+        }
+        Ok(Condition::Flag(flag))
+    }
+
+    // a skip that jumps over the next instruction when the condition is skip_when
+    fn emit_skip(&mut self, condition: Condition, skip_when: bool) -> Result<(), AsmError> {
+        let x = |register: u8| (register as u16) << 8;
+        let (condition, skip_when) = match condition {
+            Condition::NotEqual(register, operand) => {
+                (Condition::Equal(register, operand), !skip_when)
+            }
+            Condition::NotKey(register) => (Condition::Key(register), !skip_when),
+            other => (other, skip_when),
+        };
+        let word = match (condition, skip_when) {
+            (Condition::Equal(a, Operand::Byte(b)), true) => 0x3000 | x(a) | b as u16,
+            (Condition::Equal(a, Operand::Byte(b)), false) => 0x4000 | x(a) | b as u16,
+            (Condition::Equal(a, Operand::Register(b)), true) => 0x5000 | x(a) | x(b) >> 4,
+            (Condition::Equal(a, Operand::Register(b)), false) => 0x9000 | x(a) | x(b) >> 4,
+            (Condition::Key(a), true) => 0xe09e | x(a),
+            (Condition::Key(a), false) => 0xe0a1 | x(a),
+            (Condition::Flag(flag), true) => 0x3f00 | flag as u16,
+            (Condition::Flag(flag), false) => 0x4f00 | flag as u16,
+            _ => unreachable!("not equal conditions are turned around above"),
+        };
+        self.emit_word(word)
+    }
+
+    // a jump whose target is filled in later by fill_jump
+    fn emit_forward_jump(&mut self) -> Result<usize, AsmError> {
+        let at = self.here;
+        self.emit_word(0x1000)?;
+        Ok(at)
+    }
+
+    fn fill_jump(&mut self, at: usize) -> Result<(), AsmError> {
+        let word = 0x1000 | self.fit(self.here as i64, 12, "jump target")?;
+        self.write(at, &word.to_be_bytes())
+    }
+
+    fn assignment(&mut self, x: u8) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        let vx = (x as u16) << 8;
+        let source = self.peek().and_then(|token| self.register_named(token));
+        if let Some(y) = source {
+            self.next()?;
+            let vy = (y as u16) << 4;
+            let low = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xe,
+                _ => return self.error(format!("`{}` can not be used with registers", operator)),
+            };
+            return self.emit_word(0x8000 | vx | vy | low);
+        }
+        let word = match (operator.as_str(), self.peek()) {
+            (":=", Some("random")) => {
+                self.next()?;
+                0xc000 | vx | self.value(8)?
+            }
+            (":=", Some("delay")) => {
+                self.next()?;
+                0xf007 | vx
+            }
+            (":=", Some("key")) => {
+                self.next()?;
+                0xf00a | vx
+            }
+            (":=", _) => 0x6000 | vx | self.value(8)?,
+            ("+=", _) => 0x7000 | vx | self.value(8)?,
+            // there is no subtract immediate, add the negative instead
+            ("-=", _) => 0x7000 | vx | (self.value(8)? as u8).wrapping_neg() as u16,
+            _ => {
+                let token = self.next()?;
+                return self.error(format!(
+                    "`{} {}` is not a valid assignment",
+                    operator, token
+                ));
+            }
+        };
+        self.emit_word(word)
+    }
+
+    fn index_assignment(&mut self) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        match (operator.as_str(), self.peek()) {
+            ("+=", _) => {
+                let x = self.register()?;
+                self.emit_word(0xf01e | (x as u16) << 8)
+            }
+            (":=", Some("hex")) => {
+                self.next()?;
+                let x = self.register()?;
+                self.emit_word(0xf029 | (x as u16) << 8)
+            }
+            (":=", Some("bighex")) => {
+                self.next()?;
+                let x = self.register()?;
+                self.emit_word(0xf030 | (x as u16) << 8)
+            }
+            (":=", Some("long")) => {
+                self.next()?;
+                self.address_word(0xf000, Patch::Long, 16)
+            }
+            (":=", _) => self.address_word(0xa000, Patch::Address, 12),
+            _ => self.error(format!("`i {}` is not a valid assignment", operator)),
+        }
+    }
+
+    // save and load take a single register or a range, which is an xo chip instruction
+    fn register_range(&mut self, low: u16, range_low: u16) -> Result<(), AsmError> {
+        let x = self.register()?;
+        if self.peek() == Some("-") {
+            self.next()?;
+            let y = self.register()?;
+            return self.emit_word(0x5000 | (x as u16) << 8 | (y as u16) << 4 | range_low);
+        }
+        self.emit_word(0xf000 | (x as u16) << 8 | low)
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), AsmError> {
+        let line = self.line;
+        let mut values = HashMap::new();
+        for argument in &self.macros[name].arguments.clone() {
+            let value = self.next()?;
+            values.insert(argument.clone(), value);
+        }
+        let body: Vec<Token> = self.macros[name]
+            .body
+            .iter()
+            .map(|token| Token {
+                text: values.get(&token.text).unwrap_or(&token.text).clone(),
+                // errors and the source map point at the line that used the macro
+                line,
+            })
+            .collect();
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if let Some(x) = self.register_named(&token) {
+            return self.assignment(x);
+        }
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(name, self.here)?;
+            }
+            ":next" => {
+                // the second byte of the next instruction, for self modifying code
+                let name = self.next()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.next()?;
+                self.check_name(&name)?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.next()?;
+                self.check_name(&name)?;
+                let value = self.next()?;
+                match self.known_value(&value) {
+                    Some(value) => self.constants.insert(name, value),
+                    None => return self.error(format!("`{}` is not a number or constant", value)),
+                };
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.check_name(&name)?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":byte" => {
+                let byte = self.value(8)? as u8;
+                self.emit(&[byte])?;
+            }
+            ":org" => self.here = self.value(16)? as usize,
+            ":unpack" => {
+                let nibble = self.value(4)? as u8;
+                self.address_word(0, Patch::Unpack(nibble), 12)?;
+            }
+            ":macro" => {
+                let name = self.next()?;
+                self.check_name(&name)?;
+                let mut arguments = Vec::new();
+                loop {
+                    let argument = self.next()?;
+                    if argument == "{" {
+                        break;
+                    }
+                    arguments.push(argument);
+                }
+                let body = self.block()?;
+                self.macros.insert(name, Macro { arguments, body });
+            }
+            // debugger hints that do not change the rom
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "i" => self.index_assignment()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()? as u16;
+                let low = match token.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3a,
+                };
+                self.emit_word(0xf000 | x << 8 | low)?;
+            }
+            "clear" => self.emit_word(0x00e0)?,
+            "return" | ";" => self.emit_word(0x00ee)?,
+            "scroll-right" => self.emit_word(0x00fb)?,
+            "scroll-left" => self.emit_word(0x00fc)?,
+            "exit" => self.emit_word(0x00fd)?,
+            "lores" => self.emit_word(0x00fe)?,
+            "hires" => self.emit_word(0x00ff)?,
+            "audio" => self.emit_word(0xf002)?,
+            "scroll-down" => {
+                let rows = self.value(4)?;
+                self.emit_word(0x00c0 | rows)?;
+            }
+            "scroll-up" => {
+                let rows = self.value(4)?;
+                self.emit_word(0x00d0 | rows)?;
+            }
+            "plane" => {
+                let planes = self.value(4)?;
+                self.emit_word(0xf001 | planes << 8)?;
+            }
+            "bcd" => {
+                let x = self.register()? as u16;
+                self.emit_word(0xf033 | x << 8)?;
+            }
+            "save" => self.register_range(0x55, 0x2)?,
+            "load" => self.register_range(0x65, 0x3)?,
+            "saveflags" => {
+                let x = self.register()? as u16;
+                self.emit_word(0xf075 | x << 8)?;
+            }
+            "loadflags" => {
+                let x = self.register()? as u16;
+                self.emit_word(0xf085 | x << 8)?;
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let height = self.value(4)?;
+                self.emit_word(0xd000 | x << 8 | y << 4 | height)?;
+            }
+            "jump" => self.address_word(0x1000, Patch::Address, 12)?,
+            "jump0" => self.address_word(0xb000, Patch::Address, 12)?,
+            "native" => self.address_word(0x0000, Patch::Address, 12)?,
+            "if" => {
+                let condition = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.emit_skip(condition, false)?,
+                    "begin" => {
+                        self.emit_skip(condition, true)?;
+                        let jump = self.emit_forward_jump()?;
+                        self.branches.push(Branch::If(jump));
+                    }
+                    other => {
+                        return self.error(format!("expected then or begin, found `{}`", other))
+                    }
+                }
+            }
+            "else" => match self.branches.pop() {
+                Some(Branch::If(jump)) => {
+                    let end = self.emit_forward_jump()?;
+                    self.fill_jump(jump)?;
+                    self.branches.push(Branch::Else(end));
+                }
+                _ => return self.error("else without if ... begin".to_string()),
+            },
+            "end" => match self.branches.pop() {
+                Some(Branch::If(jump)) | Some(Branch::Else(jump)) => self.fill_jump(jump)?,
+                None => return self.error("end without if ... begin".to_string()),
+            },
+            "loop" => self.loops.push(Loop {
+                start: self.here,
+                exits: Vec::new(),
+            }),
+            "while" => {
+                if self.loops.is_empty() {
+                    return self.error("while outside of a loop".to_string());
+                }
+                let condition = self.condition()?;
+                self.emit_skip(condition, true)?;
+                let jump = self.emit_forward_jump()?;
+                self.loops.last_mut().unwrap().exits.push(jump);
+            }
+            "again" => {
+                let repeat = match self.loops.pop() {
+                    Some(repeat) => repeat,
+                    None => return self.error("again without loop".to_string()),
+                };
+                let start = self.fit(repeat.start as i64, 12, "loop")?;
+                self.emit_word(0x1000 | start)?;
+                for exit in repeat.exits {
+                    self.fill_jump(exit)?;
+                }
+            }
+            _ if self.macros.contains_key(&token) => self.expand_macro(&token)?,
+            // bare numbers and constants are data, usually sprites
+            _ if parse_number(&token).is_some() || self.constants.contains_key(&token) => {
+                let value = self.known_value(&token).unwrap_or_default();
+                let byte = self.fit(value.floor() as i64, 8, &token)? as u8;
+                self.emit(&[byte])?;
+            }
+            _ if KEYWORDS.contains(&token.as_str()) || token.starts_with(':') => {
+                return self.error(format!("unexpected `{}`", token))
+            }
+            // anything else is a call to a label
+            _ => {
+                self.tokens.push_front(Token {
+                    text: token,
+                    line: self.line,
+                });
+                self.address_word(0x2000, Patch::Address, 12)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Program, AsmError> {
+        if !self.branches.is_empty() {
+            return self.error("if ... begin without end".to_string());
+        }
+        if !self.loops.is_empty() {
+            return self.error("loop without again".to_string());
+        }
+        for (at, name, patch, line) in std::mem::take(&mut self.patches) {
+            self.line = line;
+            let address = match self.labels.get(&name) {
+                Some(address) => *address as i64,
+                None => return self.error(format!("`{}` is not defined", name)),
+            };
+            let (high, bits) = match patch {
+                Patch::Address => (u16::from_be_bytes([self.rom[at - ORIGIN], 0]) & 0xf000, 12),
+                Patch::Long => (0xf000, 16),
+                Patch::Unpack(_) => (0, 12),
+            };
+            let address = self.fit(address, bits, &name)?;
+            let here = std::mem::replace(&mut self.here, at);
+            self.emit_address(high, address, patch)?;
+            self.here = here;
+        }
+        self.line = 0;
+        let main = match self.labels.get("main") {
+            Some(main) => *main as i64,
+            None => return self.error("there is no `: main` to start at".to_string()),
+        };
+        let main = self.fit(main, 12, "main")?;
+        self.write(ORIGIN, &(0x1000 | main).to_be_bytes())?;
+        Ok(Program {
+            rom: self.rom,
+            source_map: self.source_map,
+        })
+    }
+}
+
+// file only names the source in error messages
+pub fn compile(source: &str, file: &str) -> Result<Program, AsmError> {
+    let mut compiler = Compiler {
+        tokens: tokenize(source),
+        file: file.to_string(),
+        line: 0,
+        rom: vec![0; 2],
+        // 0x200 holds the jump to main
+        here: ORIGIN + 2,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        patches: Vec::new(),
+        branches: Vec::new(),
+        loops: Vec::new(),
+        source_map: SourceMap::default(),
+    };
+    while !compiler.tokens.is_empty() {
+        compiler.statement()?;
+    }
+    compiler.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(source: &str) -> Vec<u8> {
+        compile(source, "test.8o").unwrap().rom
+    }
+
+    #[test]
+    fn labels_and_sprite_data() {
+        let program = compile(
+            "# draws a box\n\
+             : box 0xFF 0x81 0b10000001 0xFF\n\
+             : main\n\
+             \x20 i := box\n\
+             \x20 v0 := 10  v1 := 0x0A\n\
+             \x20 sprite v0 v1 4\n\
+             \x20 loop again\n",
+            "box.8o",
+        )
+        .unwrap();
+        assert_eq!(
+            program.rom,
+            vec![
+                0x12, 0x06, 0xff, 0x81, 0x81, 0xff, 0xa2, 0x02, 0x60, 0x0a, 0x61, 0x0a, 0xd0, 0x14,
+                0x12, 0x0e
+            ]
+        );
+        assert_eq!(program.source_map.line(0x203), Some(2));
+        assert_eq!(program.source_map.line(0x206), Some(4));
+        assert_eq!(program.source_map.line(0x20a), Some(5));
+        assert_eq!(program.source_map.line(0x20e), Some(7));
+    }
+
+    #[test]
+    fn assignments_and_forward_calls() {
+        assert_eq!(
+            rom(
+                ": main draw v3 := random 0x0F v3 += v4 v3 -= 1 v3 =- v4 v3 >>= v3 \
+                 i := long data i += v2 delay := v1 v2 := key save v0 - v3 load v5 \
+                 : draw ; : data"
+            ),
+            vec![
+                0x12, 0x02, 0x22, 0x1c, 0xc3, 0x0f, 0x83, 0x44, 0x73, 0xff, 0x83, 0x47, 0x83, 0x36,
+                0xf0, 0x00, 0x02, 0x1e, 0xf2, 0x1e, 0xf1, 0x15, 0xf2, 0x0a, 0x50, 0x32, 0xf5, 0x65,
+                0x00, 0xee
+            ]
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_eq!(
+            rom(": main if v0 == 3 then v1 := 1 \
+                 if v0 key begin clear else exit end \
+                 loop while v2 != v3 v2 += 1 again"),
+            vec![
+                0x12, 0x02, 0x40, 0x03, 0x61, 0x01, 0xe0, 0x9e, 0x12, 0x0e, 0x00, 0xe0, 0x12, 0x10,
+                0x00, 0xfd, 0x92, 0x30, 0x12, 0x18, 0x72, 0x01, 0x12, 0x10
+            ]
+        );
+    }
+
+    #[test]
+    fn comparisons_run_correctly() {
+        use crate::interperter::Chip8;
+        for comparison in ["<", ">", "<=", ">="] {
+            for (a, b) in [(3, 5), (5, 5), (6, 5)] {
+                for right in ["v1".to_string(), b.to_string()] {
+                    let source = format!(
+                        ": main v0 := {} v1 := {} v2 := 0 if v0 {} {} then v2 := 1 \
+                         : halt jump halt",
+                        a, b, comparison, right
+                    );
+                    let mut chip8 = Chip8::init();
+                    chip8.load_rom(&rom(&source)).unwrap();
+                    while !chip8.is_spinning() {
+                        chip8.step().unwrap();
+                    }
+                    let expected = match comparison {
+                        "<" => a < b,
+                        ">" => a > b,
+                        "<=" => a <= b,
+                        _ => a >= b,
+                    };
+                    assert_eq!(chip8.v_register[2], expected as u8, "{}", source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn macros_calc_and_alias() {
+        assert_eq!(
+            rom(":alias x v4 \
+                 :const SPEED 2 \
+                 :calc FAST { SPEED * ( 1 + 2 ) } \
+                 :macro move register amount { register += amount } \
+                 : main move x FAST :byte { FAST + 1 } \
+                 :unpack 0xA main"),
+            vec![0x12, 0x02, 0x74, 0x06, 0x07, 0x60, 0xa2, 0x61, 0x02]
+        );
+        // right to left without precedence, like octo
+        assert_eq!(rom(":calc X { 2 * 3 + 1 } : main X"), vec![0x12, 0x02, 8]);
+    }
+
+    #[test]
+    fn errors_point_at_lines() {
+        let error = compile(": main\n  v0 := 300\n", "game.8o").unwrap_err();
+        assert_eq!(error.to_string(), "game.8o:2: `300` does not fit in 8 bits");
+        assert_eq!(
+            compile("v0 := 1", "x").unwrap_err().message,
+            "there is no `: main` to start at"
+        );
+        assert_eq!(compile(": main\n\njump nowhere", "x").unwrap_err().line, 3);
+        assert_eq!(
+            compile(": main\nloop\n", "x").unwrap_err().message,
+            "loop without again"
+        );
+        assert_eq!(
+            compile(": main : main", "x").unwrap_err().message,
+            "`main` is already defined"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use chip8::audio::{ToneSettings, Waveform};
+use chip8::settings::Settings;
+
+pub const USAGE: &str = "usage: chip8 <rom> [options]
+
+  --scale N            window pixels per chip8 pixel, default 10
+  --speed N            instructions per frame, default 9
+  --ips N              instructions per second instead, spread over the frames
+  --vip-timing         run as many instructions as a COSMAC VIP managed each frame
+  --platform NAME      chip8, schip or xochip
+  --quirks NAME        vip, chip48, schip, xochip or modern
+  --foreground RRGGBB  colour of lit pixels, default ffffff
+  --background RRGGBB  colour of unlit pixels, default 000000
+  --tone-frequency HZ  pitch of the beep, default 440
+  --volume N           loudness of the beep from 0 to 1, default 0.25
+  --waveform NAME      square, sine or triangle, default square
+  --paused             start paused, press P or type continue to run
+  --fullscreen         fill the screen instead of opening a window
+  --windowless         draw in the terminal instead of a window, without keypad input
+  --keymap FILE        key bindings over the defaults, see --print-keymap
+  --print-keymap       print the default key bindings in the keymap file format
+  --help               show this
+
+while playing P pauses, F10 restarts, Esc quits, F12 records a movie,
+backspace rewinds, F1-F9 load a save state and shift F1-F9 save one. debugger
+commands are typed into the terminal, try help
+
+keymap files are read from ~/.config/chip8/keymap and from <rom>.keymap next to
+the rom, then --keymap, each one only changing the bindings it lists
+
+roms the built in database knows get its platform, quirks, speed, colours and
+arrow key bindings, the options above override them";
+
+pub struct Options {
+    pub rom: String,
+    pub scale: u32,
+    pub settings: Settings,
+    pub tone: ToneSettings,
+    pub paused: bool,
+    pub fullscreen: bool,
+    pub windowless: bool,
+    pub keymap: Option<String>,
+}
+
+pub fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom: String::new(),
+        scale: 10,
+        settings: Settings::default(),
+        tone: ToneSettings::default(),
+        paused: false,
+        fullscreen: false,
+        windowless: false,
+        keymap: None,
+    };
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        let bad_value = |value: &str| format!("bad value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--scale" => {
+                let scale = value()?;
+                options.scale = scale
+                    .parse()
+                    .ok()
+                    .filter(|scale| *scale > 0)
+                    .ok_or_else(|| bad_value(&scale))?;
+            }
+            "--tone-frequency" => {
+                let frequency = value()?;
+                options.tone.frequency = frequency
+                    .parse()
+                    .ok()
+                    .filter(|frequency: &f32| *frequency > 0.0)
+                    .ok_or_else(|| bad_value(&frequency))?;
+            }
+            "--volume" => {
+                let volume = value()?;
+                options.tone.volume = volume
+                    .parse()
+                    .ok()
+                    .filter(|volume| (0.0..=1.0).contains(volume))
+                    .ok_or_else(|| bad_value(&volume))?;
+            }
+            "--waveform" => {
+                let name = value()?;
+                options.tone.waveform =
+                    Waveform::from_name(&name).ok_or_else(|| bad_value(&name))?;
+            }
+            "--paused" => options.paused = true,
+            "--fullscreen" => options.fullscreen = true,
+            "--windowless" => options.windowless = true,
+            "--keymap" => options.keymap = Some(value()?),
+            _ if options.settings.parse_flag(arg, &mut value)? => {}
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if rom.is_none() => rom = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    options.rom = rom.ok_or_else(|| "no rom given".to_string())?;
+    if options.windowless && options.fullscreen {
+        return Err("--fullscreen needs a window".to_string());
+    }
+    Ok(options)
+}
@@ -0,0 +1,96 @@
+// the original chip8 left a few instructions loosely defined and later interpreters
+// picked different answers, so roms only behave right with the set they were written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // SHR and SHL shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // LD_IV and LD_VI leave I pointing past the last register they touched
+    pub load_store_increments_i: bool,
+    // OR, AND and XOR set VF to 0
+    pub logic_resets_vf: bool,
+    // JP_VA jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // DRW cuts sprites off at the screen edges instead of wrapping them around
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        logic_resets_vf: true,
+        jump_uses_vx: false,
+        clip_sprites: true,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        logic_resets_vf: false,
+        jump_uses_vx: true,
+        clip_sprites: true,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        logic_resets_vf: false,
+        jump_uses_vx: true,
+        clip_sprites: true,
+    };
+
+    // what octo does out of the box
+    pub const MODERN: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        logic_resets_vf: false,
+        jump_uses_vx: false,
+        clip_sprites: false,
+    };
+
+    // xo chip came out of octo and keeps its quirks
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        logic_resets_vf: false,
+        jump_uses_vx: false,
+        clip_sprites: false,
+    };
+
+    // packs the quirks into one byte for save state headers
+    pub fn to_bits(self) -> u8 {
+        (self.shift_uses_vy as u8)
+            | (self.load_store_increments_i as u8) << 1
+            | (self.logic_resets_vf as u8) << 2
+            | (self.jump_uses_vx as u8) << 3
+            | (self.clip_sprites as u8) << 4
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        Quirks {
+            shift_uses_vy: bits & 0x01 != 0,
+            load_store_increments_i: bits & 0x02 != 0,
+            logic_resets_vf: bits & 0x04 != 0,
+            jump_uses_vx: bits & 0x08 != 0,
+            clip_sprites: bits & 0x10 != 0,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac" | "cosmac-vip" | "chip8" | "chip-8" => Some(Quirks::COSMAC_VIP),
+            "chip48" | "chip-48" => Some(Quirks::CHIP_48),
+            "schip" | "superchip" | "super-chip" => Some(Quirks::SUPER_CHIP),
+            "xochip" | "xo-chip" => Some(Quirks::XO_CHIP),
+            "modern" | "octo" => Some(Quirks::MODERN),
+            _ => None,
+        }
+    }
+}
+
+// none of the quirks, how this interpreter ran every rom before it had them
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::from_bits(0)
+    }
+}
@@ -0,0 +1,90 @@
+// the random numbers behind RND. the chip8 owns its generator so a run can be repeated
+// exactly by reusing the seed, which replays, movies and tests all depend on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomAlgorithm {
+    // xorshift64*, fast and well distributed. the only one for now, the vip way of
+    // reading bytes out of its interpreter page needs a copy of that interpreter
+    Xorshift,
+}
+
+#[derive(Debug, Clone)]
+pub struct Random {
+    algorithm: RandomAlgorithm,
+    seed: u64,
+    state: u64,
+}
+
+impl Random {
+    pub fn new(algorithm: RandomAlgorithm, seed: u64) -> Random {
+        let mut random = Random {
+            algorithm,
+            seed,
+            state: 0,
+        };
+        random.reseed(seed);
+        random
+    }
+
+    pub fn algorithm(&self) -> RandomAlgorithm {
+        self.algorithm
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        // xorshift gets stuck on zero so the seed is mixed into a non zero state
+        self.state = seed ^ 0x9e37_79b9_7f4a_7c15;
+        if self.state == 0 {
+            self.state = 0x9e37_79b9_7f4a_7c15;
+        }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        match self.algorithm {
+            RandomAlgorithm::Xorshift => {
+                self.state ^= self.state >> 12;
+                self.state ^= self.state << 25;
+                self.state ^= self.state >> 27;
+                (self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8
+            }
+        }
+    }
+
+    // for save states
+    pub(crate) fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub(crate) fn restore(algorithm: RandomAlgorithm, seed: u64, state: u64) -> Random {
+        Random {
+            algorithm,
+            seed,
+            state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_bytes() {
+        let mut first = Random::new(RandomAlgorithm::Xorshift, 1234);
+        let mut second = Random::new(RandomAlgorithm::Xorshift, 1234);
+        let first_bytes: Vec<u8> = (0..32).map(|_| first.next_byte()).collect();
+        let second_bytes: Vec<u8> = (0..32).map(|_| second.next_byte()).collect();
+        assert_eq!(first_bytes, second_bytes);
+        assert!(first_bytes.iter().any(|byte| *byte != first_bytes[0]));
+    }
+
+    #[test]
+    fn zero_seed_still_random() {
+        let mut random = Random::new(RandomAlgorithm::Xorshift, 0);
+        let bytes: Vec<u8> = (0..8).map(|_| random.next_byte()).collect();
+        assert!(bytes.iter().any(|byte| *byte != 0));
+    }
+}
@@ -1,35 +1,67 @@
+use chip8::DisplayBackend;
 use sdl2::pixels::Color;
-use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::*;
 use sdl2::render::Canvas;
+use sdl2::video::Window;
 
-pub fn make_pixels(screen_height: i32, screen_width: i32) -> Vec<Rect> {
+pub fn make_pixels(screen_width: usize, screen_height: usize) -> Vec<Rect> {
+    // one rect per chip8 pixel, the canvas scale takes care of the window size
     let mut pixel_vec = Vec::new();
-    for row in 0..32 {
-        for column in 0..64 {
-            pixel_vec.push(Rect::new(
-                column * screen_width / 32,
-                row * screen_height / 64,
-                screen_width as u32 / 64,
-                screen_height as u32 / 32,
-            ))
+    for row in 0..screen_height {
+        for column in 0..screen_width {
+            pixel_vec.push(Rect::new(column as i32, row as i32, 1, 1))
         }
     }
     pixel_vec
 }
 
-pub fn color_pixels(
-    gfx: &[u8; 32 * 64],
-    pixel_vec: &mut Vec<Rect>,
-    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-) {
-    for index in 0..(32 * 64) {
-        if gfx[index] > 0 {
-            canvas.set_draw_color(Color::RGB(255, 255, 255));
-            canvas.fill_rect(pixel_vec[index]);
-        } else {
-            canvas.set_draw_color(Color::RGB(0, 0, 0));
-            canvas.fill_rect(pixel_vec[index]);
+pub struct SdlDisplay {
+    canvas: Canvas<Window>,
+    pixels: Vec<Rect>,
+    gfx: Vec<u8>,
+    palette: [[u8; 3]; 4],
+}
+
+impl SdlDisplay {
+    pub fn new(canvas: Canvas<Window>, palette: [[u8; 3]; 4]) -> SdlDisplay {
+        SdlDisplay {
+            canvas,
+            pixels: Vec::new(),
+            gfx: Vec::new(),
+            palette,
+        }
+    }
+
+    fn color_pixels(&mut self) {
+        for (pixel, rect) in self.gfx.iter().zip(self.pixels.iter()) {
+            let [red, green, blue] = self.palette[(*pixel & 0x03) as usize];
+            self.canvas.set_draw_color(Color::RGB(red, green, blue));
+            self.canvas.fill_rect(*rect).unwrap();
         }
     }
 }
+
+impl DisplayBackend for SdlDisplay {
+    fn draw(&mut self, gfx: &[u8], width: usize, height: usize) {
+        if self.pixels.len() != width * height {
+            // resolution changed (super chip high resolution mode), rescale to fill the window
+            self.pixels = make_pixels(width, height);
+            let (window_width, window_height) = self.canvas.output_size().unwrap();
+            self.canvas
+                .set_scale(
+                    window_width as f32 / width as f32,
+                    window_height as f32 / height as f32,
+                )
+                .unwrap();
+        }
+        self.gfx.clear();
+        self.gfx.extend_from_slice(gfx);
+    }
+
+    fn present(&mut self) {
+        // the back buffer is not kept between presents so the whole frame is redrawn
+        self.canvas.clear();
+        self.color_pixels();
+        self.canvas.present();
+    }
+}
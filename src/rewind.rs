@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+
+// keeps recent save states so execution can be played backwards a frame at a time.
+// only the newest state is kept whole, every older one is stored as the xor against
+// the state after it with the runs of zeros squeezed out. most frames only change a
+// few bytes so each step back costs very little, and the oldest steps can be thrown
+// away to stay inside the memory budget without breaking the newer ones
+pub struct RewindBuffer {
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+    budget_bytes: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(budget_bytes: usize) -> RewindBuffer {
+        RewindBuffer {
+            current: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            budget_bytes,
+        }
+    }
+
+    // called once per frame with Chip8::save_state
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(current) = self.current.take() {
+            let delta = encode_delta(&state, &current);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.current = Some(state);
+        while self.memory_used() > self.budget_bytes {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.delta_bytes -= oldest.len(),
+                None => break,
+            }
+        }
+    }
+
+    // steps one frame back and returns that state, None once the history runs out
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.delta_bytes -= delta.len();
+        let current = self.current.take()?;
+        let previous = apply_delta(&current, &delta);
+        self.current = Some(previous.clone());
+        Some(previous)
+    }
+
+    // number of frames that can be stepped back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.delta_bytes + self.current.as_ref().map_or(0, |state| state.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while *position < data.len() {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+// delta layout: target length, then pairs of (zero run length, literal length, literals)
+// where the literals are the xor of the two states
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let length = from.len().max(to.len());
+    let xor = |index: usize| from.get(index).unwrap_or(&0) ^ to.get(index).unwrap_or(&0);
+    let mut delta = Vec::new();
+    write_varint(&mut delta, to.len());
+    let mut index = 0;
+    while index < length {
+        let zeros_start = index;
+        while index < length && xor(index) == 0 {
+            index += 1;
+        }
+        let literal_start = index;
+        while index < length && xor(index) != 0 {
+            index += 1;
+        }
+        write_varint(&mut delta, literal_start - zeros_start);
+        write_varint(&mut delta, index - literal_start);
+        delta.extend((literal_start..index).map(xor));
+    }
+    delta
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let length = read_varint(delta, &mut position);
+    let mut to = from.to_vec();
+    to.resize(length.max(from.len()), 0);
+    let mut index = 0;
+    while position < delta.len() {
+        index += read_varint(delta, &mut position);
+        let literals = read_varint(delta, &mut position);
+        for byte in &delta[position..position + literals] {
+            to[index] ^= byte;
+            index += 1;
+        }
+        position += literals;
+    }
+    to.truncate(length);
+    to
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_round_trip() {
+        let from = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let to = vec![1, 2, 9, 4, 5, 6, 0, 8, 10];
+        let delta = encode_delta(&to, &from);
+        assert_eq!(apply_delta(&to, &delta), from);
+        let delta = encode_delta(&from, &to);
+        assert_eq!(apply_delta(&from, &delta), to);
+    }
+
+    #[test]
+    fn rewind_steps_back_in_order() {
+        let mut rewind = RewindBuffer::new(1 << 20);
+        for frame in 0..5u8 {
+            let mut state = vec![0u8; 1000];
+            state[10] = frame;
+            rewind.push(state);
+        }
+        assert_eq!(rewind.len(), 4);
+        for frame in (0..4u8).rev() {
+            assert_eq!(rewind.pop().unwrap()[10], frame);
+        }
+        assert!(rewind.pop().is_none());
+    }
+
+    #[test]
+    fn rewind_stays_in_budget() {
+        let mut rewind = RewindBuffer::new(1500);
+        for frame in 0..1000u32 {
+            let mut state = vec![0u8; 1000];
+            state[..4].copy_from_slice(&frame.to_be_bytes());
+            rewind.push(state);
+        }
+        assert!(rewind.memory_used() <= 1500);
+        assert!(rewind.len() > 10);
+        assert_eq!(rewind.pop().unwrap()[..4], 998u32.to_be_bytes());
+    }
+
+    #[test]
+    fn rewind_chip8_states() {
+        let mut chip8 = crate::Chip8::init();
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind = RewindBuffer::new(1 << 20);
+        for _ in 0..20 {
+            chip8.step().unwrap();
+            rewind.push(chip8.save_state());
+        }
+        assert_eq!(chip8.v_register[0], 10);
+        for _ in 0..4 {
+            chip8.load_state(&rewind.pop().unwrap()).unwrap();
+        }
+        assert_eq!(chip8.v_register[0], 8);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::timing::VIP_CYCLES_PER_FRAME;
+use std::time::Duration;
+
+// decides how many 60 Hz ticks are due and how much each one runs, so game speed and
+// timers follow the wall clock instead of the monitor refresh rate. the frontend
+// measures the time between calls to advance and runs that many ticks, each one
+// being a frame of instructions followed by one timer decrement. a frame is a count
+// of instructions or, on VIP timing, a budget of machine cycles
+
+pub const TICKS_PER_SECOND: u32 = 60;
+
+// falling further behind than this (the window was dragged, the machine stalled)
+// drops the missed ticks instead of racing through them
+pub const MAX_CATCH_UP_TICKS: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    InstructionsPerFrame(u32),
+    // spread as evenly as possible over the ticks of each second
+    InstructionsPerSecond(u32),
+    // as many instructions as fit in the cycles a COSMAC VIP had each frame
+    CosmacVip,
+}
+
+// what a single tick gets to spend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBudget {
+    Instructions(u32),
+    // machine cycles, for Chip8::run_frame_cycles
+    Cycles(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    speed: Speed,
+    // wall clock time not yet turned into ticks, in nanoseconds
+    pending: u64,
+    // which tick of the second comes next, for spreading instructions per second
+    tick_in_second: u32,
+    dropped_ticks: u64,
+}
+
+const TICK_NANOS: u64 = 1_000_000_000 / TICKS_PER_SECOND as u64;
+
+impl Scheduler {
+    pub fn new(speed: Speed) -> Scheduler {
+        Scheduler {
+            speed,
+            pending: 0,
+            tick_in_second: 0,
+            dropped_ticks: 0,
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    // the whole number of instructions a tick runs on average. None on VIP timing
+    // where it changes with every instruction
+    pub fn instructions_per_frame(&self) -> Option<u32> {
+        match self.speed {
+            Speed::InstructionsPerFrame(instructions) => Some(instructions),
+            Speed::InstructionsPerSecond(instructions) => {
+                Some((instructions + TICKS_PER_SECOND / 2) / TICKS_PER_SECOND)
+            }
+            Speed::CosmacVip => None,
+        }
+    }
+
+    // adds the time since the last call and returns how many ticks to run now
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.pending = self.pending.saturating_add(elapsed.as_nanos() as u64);
+        let due = self.pending / TICK_NANOS;
+        self.pending %= TICK_NANOS;
+        if due > MAX_CATCH_UP_TICKS as u64 {
+            self.dropped_ticks += due - MAX_CATCH_UP_TICKS as u64;
+            return MAX_CATCH_UP_TICKS;
+        }
+        due as u32
+    }
+
+    // how long the frontend can sleep before the next tick is due
+    pub fn time_until_next_tick(&self) -> Duration {
+        Duration::from_nanos(TICK_NANOS - self.pending)
+    }
+
+    // what the next tick runs, call once per tick
+    pub fn budget_for_tick(&mut self) -> FrameBudget {
+        match self.speed {
+            Speed::InstructionsPerFrame(instructions) => FrameBudget::Instructions(instructions),
+            Speed::InstructionsPerSecond(instructions) => {
+                // the share of this second up to the end of the tick minus the share
+                // up to its start, so a second always adds up to the exact rate
+                let tick = self.tick_in_second as u64;
+                let per_second = instructions as u64;
+                let ticks = TICKS_PER_SECOND as u64;
+                let count = per_second * (tick + 1) / ticks - per_second * tick / ticks;
+                self.tick_in_second = (self.tick_in_second + 1) % TICKS_PER_SECOND;
+                FrameBudget::Instructions(count as u32)
+            }
+            Speed::CosmacVip => FrameBudget::Cycles(VIP_CYCLES_PER_FRAME),
+        }
+    }
+
+    // ticks skipped because the frontend fell too far behind
+    pub fn dropped_ticks(&self) -> u64 {
+        self.dropped_ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_follow_the_clock() {
+        let mut scheduler = Scheduler::new(Speed::InstructionsPerFrame(9));
+        assert_eq!(scheduler.advance(Duration::from_millis(10)), 0);
+        assert_eq!(scheduler.advance(Duration::from_millis(10)), 1);
+        assert_eq!(
+            scheduler.time_until_next_tick(),
+            Duration::from_nanos(2 * TICK_NANOS - 20_000_000)
+        );
+        // a second split into odd slices is still 60 ticks
+        let mut scheduler = Scheduler::new(Speed::InstructionsPerFrame(9));
+        let ticks: u32 = (0..70)
+            .map(|_| scheduler.advance(Duration::from_nanos(1_000_000_000 / 70)))
+            .sum();
+        assert_eq!(ticks, 60);
+        assert_eq!(scheduler.budget_for_tick(), FrameBudget::Instructions(9));
+    }
+
+    #[test]
+    fn long_stalls_are_dropped() {
+        let mut scheduler = Scheduler::new(Speed::InstructionsPerFrame(9));
+        assert_eq!(
+            scheduler.advance(Duration::from_secs(1)),
+            MAX_CATCH_UP_TICKS
+        );
+        assert_eq!(scheduler.dropped_ticks(), 60 - MAX_CATCH_UP_TICKS as u64);
+        assert_eq!(scheduler.advance(Duration::from_millis(1)), 0);
+    }
+
+    #[test]
+    fn instructions_per_second_are_spread_out() {
+        let mut scheduler = Scheduler::new(Speed::InstructionsPerSecond(700));
+        let mut instructions = || match scheduler.budget_for_tick() {
+            FrameBudget::Instructions(count) => count,
+            FrameBudget::Cycles(_) => panic!("expected instructions"),
+        };
+        let counts: Vec<u32> = (0..60).map(|_| instructions()).collect();
+        assert_eq!(counts.iter().sum::<u32>(), 700);
+        assert!(counts.iter().all(|count| *count == 11 || *count == 12));
+        // and the next second starts over
+        assert_eq!((0..60).map(|_| instructions()).sum::<u32>(), 700);
+        assert_eq!(scheduler.instructions_per_frame(), Some(12));
+    }
+}
@@ -0,0 +1,57 @@
+use chip8::audio::{AudioPattern, Synth, ToneSettings};
+use chip8::AudioBackend;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+struct Beep {
+    synth: Synth,
+}
+
+impl AudioCallback for Beep {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.synth.fill(out);
+    }
+}
+
+// plays the tone through the default sdl audio device while the sound timer runs
+pub struct SdlBeeper {
+    device: AudioDevice<Beep>,
+    playing: bool,
+}
+
+impl SdlBeeper {
+    pub fn new(
+        audio_subsystem: &AudioSubsystem,
+        settings: ToneSettings,
+    ) -> Result<SdlBeeper, String> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| Beep {
+            synth: Synth::new(settings, spec.freq as u32),
+        })?;
+        // the device runs all the time and the synth plays silence while the timer is zero
+        device.resume();
+        Ok(SdlBeeper {
+            device,
+            playing: false,
+        })
+    }
+}
+
+impl AudioBackend for SdlBeeper {
+    fn set_playing(&mut self, playing: bool) {
+        if playing != self.playing {
+            self.device.lock().synth.set_playing(playing);
+            self.playing = playing;
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: AudioPattern) {
+        self.device.lock().synth.set_pattern(pattern);
+    }
+}
@@ -0,0 +1,99 @@
+use chip8::keymap::{Action, Binding, HostKey, Keymap};
+use chip8::{KeyEvent, KeypadInput};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+
+// collects keypad changes out of the sdl event pump until the next poll
+pub struct SdlKeypad {
+    pending: Vec<KeyEvent>,
+    keycodes: Vec<(Keycode, Binding)>,
+    scancodes: Vec<(Scancode, Binding)>,
+    // how many host keys are holding each chip8 key down, so letting go of one of
+    // two keys bound to it does not release it
+    held: [u8; 16],
+}
+
+impl SdlKeypad {
+    pub fn new(keymap: &Keymap) -> Result<SdlKeypad, String> {
+        let mut keycodes = Vec::new();
+        let mut scancodes = Vec::new();
+        for (host, binding) in keymap.bindings() {
+            match host {
+                HostKey::Keycode(name) => keycodes.push((
+                    Keycode::from_name(name).ok_or_else(|| format!("unknown key {}", name))?,
+                    *binding,
+                )),
+                HostKey::Scancode(name) => scancodes.push((
+                    Scancode::from_name(name)
+                        .ok_or_else(|| format!("unknown scancode {}", name))?,
+                    *binding,
+                )),
+            }
+        }
+        Ok(SdlKeypad {
+            pending: Vec::new(),
+            keycodes,
+            scancodes,
+            held: [0; 16],
+        })
+    }
+
+    fn bindings(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Vec<Binding> {
+        let by_keycode = self
+            .keycodes
+            .iter()
+            .filter(|(bound, _)| Some(*bound) == keycode);
+        let by_scancode = self
+            .scancodes
+            .iter()
+            .filter(|(bound, _)| Some(*bound) == scancode);
+        by_keycode
+            .map(|(_, binding)| *binding)
+            .chain(by_scancode.map(|(_, binding)| *binding))
+            .collect()
+    }
+
+    // queues the keypad changes in event and returns the actions it presses (true)
+    // or releases (false)
+    pub fn handle_event(&mut self, event: &Event) -> Vec<(Action, bool)> {
+        let (keycode, scancode, pressed) = match event {
+            Event::KeyDown {
+                keycode,
+                scancode,
+                repeat: false,
+                ..
+            } => (*keycode, *scancode, true),
+            Event::KeyUp {
+                keycode, scancode, ..
+            } => (*keycode, *scancode, false),
+            _ => return Vec::new(),
+        };
+        let mut actions = Vec::new();
+        for binding in self.bindings(keycode, scancode) {
+            match binding {
+                Binding::Action(action) => actions.push((action, pressed)),
+                Binding::Key(key) => {
+                    let held = &mut self.held[key as usize];
+                    if pressed {
+                        *held += 1;
+                        if *held == 1 {
+                            self.pending.push(KeyEvent::Pressed(key));
+                        }
+                    } else if *held > 0 {
+                        *held -= 1;
+                        if *held == 0 {
+                            self.pending.push(KeyEvent::Released(key));
+                        }
+                    }
+                }
+            }
+        }
+        actions
+    }
+}
+
+impl KeypadInput for SdlKeypad {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        self.pending.drain(..).collect()
+    }
+}
@@ -0,0 +1,165 @@
+// the command line options both frontends take for how a rom runs and looks. each
+// one is None when left to the rom database or the defaults
+use crate::database::RomInfo;
+use crate::display::{parse_colour, DEFAULT_PALETTE};
+use crate::error::Chip8Error;
+use crate::interperter::Chip8;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::scheduler::Speed;
+use std::path::Path;
+
+// what a rom runs at when neither the options nor the database say
+pub const DEFAULT_SPEED: Speed = Speed::InstructionsPerFrame(9);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub speed: Option<Speed>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub foreground: Option<[u8; 3]>,
+    pub background: Option<[u8; 3]>,
+}
+
+impl Settings {
+    // takes flag if it sets one of the fields above, calling value for its argument.
+    // false leaves it to the frontend
+    pub fn parse_flag<F>(&mut self, flag: &str, mut value: F) -> Result<bool, String>
+    where
+        F: FnMut() -> Result<String, String>,
+    {
+        let bad_value = |value: &str| format!("bad value for {}: {}", flag, value);
+        match flag {
+            "--speed" => {
+                let speed = value()?;
+                self.speed = Some(Speed::InstructionsPerFrame(
+                    speed.parse().map_err(|_| bad_value(&speed))?,
+                ));
+            }
+            "--ips" => {
+                let speed = value()?;
+                self.speed = Some(Speed::InstructionsPerSecond(
+                    speed.parse().map_err(|_| bad_value(&speed))?,
+                ));
+            }
+            "--vip-timing" => self.speed = Some(Speed::CosmacVip),
+            "--platform" => {
+                let name = value()?;
+                self.platform = Some(Platform::from_name(&name).ok_or_else(|| bad_value(&name))?);
+            }
+            "--quirks" => {
+                let name = value()?;
+                self.quirks = Some(Quirks::from_name(&name).ok_or_else(|| bad_value(&name))?);
+            }
+            "--foreground" => {
+                let colour = value()?;
+                self.foreground = Some(parse_colour(&colour).ok_or_else(|| bad_value(&colour))?);
+            }
+            "--background" => {
+                let colour = value()?;
+                self.background = Some(parse_colour(&colour).ok_or_else(|| bad_value(&colour))?);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    // loads the game on the platform asked for and then sets the quirks asked for,
+    // both over what the rom database lists
+    pub fn load_game<P: AsRef<Path>>(&self, chip8: &mut Chip8, path: P) -> Result<(), Chip8Error> {
+        chip8.load_game_on(path, self.platform)?;
+        if let Some(quirks) = self.quirks {
+            chip8.quirks = quirks;
+        }
+        Ok(())
+    }
+
+    pub fn speed_for(&self, info: Option<&RomInfo>) -> Speed {
+        let tickrate = info.and_then(|info| info.tickrate);
+        self.speed
+            .or_else(|| tickrate.map(Speed::InstructionsPerFrame))
+            .unwrap_or(DEFAULT_SPEED)
+    }
+
+    // the database colours over the defaults, then the colours asked for
+    pub fn palette_for(&self, info: Option<&RomInfo>) -> [[u8; 3]; 4] {
+        let mut palette = DEFAULT_PALETTE;
+        for (entry, colour) in palette
+            .iter_mut()
+            .zip(info.map_or(&[][..], |info| &info.colours))
+        {
+            *entry = *colour;
+        }
+        if let Some(background) = self.background {
+            palette[0] = background;
+        }
+        if let Some(foreground) = self.foreground {
+            palette[1] = foreground;
+        }
+        palette
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = || {
+                args.next()
+                    .map(|value| value.to_string())
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            assert!(settings.parse_flag(flag, value)?, "{} not taken", flag);
+        }
+        Ok(settings)
+    }
+
+    #[test]
+    fn flags_and_database_defaults() {
+        let settings = parse(&[
+            "--ips",
+            "600",
+            "--platform",
+            "xochip",
+            "--background",
+            "#102030",
+        ])
+        .unwrap();
+        assert_eq!(settings.speed, Some(Speed::InstructionsPerSecond(600)));
+        assert_eq!(settings.platform, Some(Platform::XoChip));
+        assert_eq!(
+            parse(&["--quirks", "nope"]).unwrap_err(),
+            "bad value for --quirks: nope"
+        );
+        assert!(!Settings::default()
+            .parse_flag("--frames", || Ok(String::new()))
+            .unwrap());
+
+        let info = RomInfo {
+            title: String::new(),
+            authors: Vec::new(),
+            platform: None,
+            quirks: None,
+            tickrate: Some(15),
+            colours: vec![[1, 1, 1], [2, 2, 2], [3, 3, 3]],
+            keys: Vec::new(),
+        };
+        assert_eq!(
+            settings.speed_for(Some(&info)),
+            Speed::InstructionsPerSecond(600)
+        );
+        assert_eq!(
+            Settings::default().speed_for(Some(&info)),
+            Speed::InstructionsPerFrame(15)
+        );
+        assert_eq!(Settings::default().speed_for(None), DEFAULT_SPEED);
+        assert_eq!(
+            settings.palette_for(Some(&info)),
+            [[0x10, 0x20, 0x30], [2, 2, 2], [3, 3, 3], DEFAULT_PALETTE[3]]
+        );
+    }
+}
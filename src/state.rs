@@ -0,0 +1,291 @@
+use crate::error::Chip8Error;
+use crate::interperter::{Chip8, HIRES_HEIGHT, HIRES_WIDTH, STACK_DEPTH};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::random::{Random, RandomAlgorithm};
+
+// save state layout, all numbers big endian:
+//   header  "CH8S", version u16, rom sha-1 [20], quirks u8, platform u8
+//   machine memory length u32, memory, v registers [16], I u16, pc u16,
+//           stack [17 x u16], sp u16, delay timer, sound timer, hires, planes,
+//           gfx [128 x 64], keys [16], rpl flags [16], halted,
+//           audio pattern present, audio pattern [16], pitch,
+//           random algorithm u8, random seed u64, random state u64
+// bump the version whenever this changes so old states are rejected instead of misread
+const MAGIC: &[u8; 4] = b"CH8S";
+pub const SAVE_STATE_VERSION: u16 = 3;
+
+struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], Chip8Error> {
+        if self.position + length > self.data.len() {
+            return Err(Chip8Error::InvalidSaveState(
+                "state is truncated".to_string(),
+            ));
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, Chip8Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, Chip8Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Chip8Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, Chip8Error> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+fn platform_to_byte(platform: Platform) -> u8 {
+    match platform {
+        Platform::Chip8 => 0,
+        Platform::SuperChip => 1,
+        Platform::XoChip => 2,
+    }
+}
+
+fn random_from_byte(byte: u8) -> Result<RandomAlgorithm, Chip8Error> {
+    match byte {
+        0 => Ok(RandomAlgorithm::Xorshift),
+        1 => Err(Chip8Error::InvalidSaveState(
+            "the cosmac vip random generator is no longer supported".to_string(),
+        )),
+        _ => Err(Chip8Error::InvalidSaveState(format!(
+            "unknown random algorithm {}",
+            byte
+        ))),
+    }
+}
+
+fn platform_from_byte(byte: u8) -> Result<Platform, Chip8Error> {
+    match byte {
+        0 => Ok(Platform::Chip8),
+        1 => Ok(Platform::SuperChip),
+        2 => Ok(Platform::XoChip),
+        _ => Err(Chip8Error::InvalidSaveState(format!(
+            "unknown platform {}",
+            byte
+        ))),
+    }
+}
+
+impl Chip8 {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(self.memory.len() + HIRES_WIDTH * HIRES_HEIGHT + 256);
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&SAVE_STATE_VERSION.to_be_bytes());
+        state.extend_from_slice(&self.rom_hash);
+        state.push(self.quirks.to_bits());
+        state.push(platform_to_byte(self.platform));
+
+        state.extend_from_slice(&(self.memory.len() as u32).to_be_bytes());
+        state.extend_from_slice(&self.memory);
+        state.extend_from_slice(&self.v_register);
+        state.extend_from_slice(&self.I.to_be_bytes());
+        state.extend_from_slice(&self.pc.to_be_bytes());
+        for address in &self.stack {
+            state.extend_from_slice(&address.to_be_bytes());
+        }
+        state.extend_from_slice(&self.sp.to_be_bytes());
+        state.push(self.delay_timer);
+        state.push(self.sound_timer);
+        state.push(self.hires as u8);
+        state.push(self.planes);
+        state.extend_from_slice(&self.gfx);
+        state.extend(self.key.iter().map(|pressed| *pressed as u8));
+        state.extend_from_slice(&self.rpl_flags);
+        state.push(self.halted as u8);
+        state.push(self.audio_pattern.is_some() as u8);
+        state.extend_from_slice(&self.audio_pattern.unwrap_or([0; 16]));
+        state.push(self.pitch);
+        state.push(match self.random.algorithm() {
+            RandomAlgorithm::Xorshift => 0,
+        });
+        state.extend_from_slice(&self.random.seed().to_be_bytes());
+        state.extend_from_slice(&self.random.state().to_be_bytes());
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        // everything is read and checked before the machine is touched so a bad
+        // state leaves it as it was
+        let mut reader = StateReader {
+            data: state,
+            position: 0,
+        };
+        if reader.bytes(4)? != MAGIC {
+            return Err(Chip8Error::InvalidSaveState("not a save state".to_string()));
+        }
+        let version = reader.u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(Chip8Error::InvalidSaveState(format!(
+                "version {} is not supported, expected {}",
+                version, SAVE_STATE_VERSION
+            )));
+        }
+        if reader.bytes(20)? != self.rom_hash {
+            return Err(Chip8Error::SaveStateRomMismatch);
+        }
+        let quirks = Quirks::from_bits(reader.u8()?);
+        let platform = platform_from_byte(reader.u8()?)?;
+
+        let memory_length = reader.u32()? as usize;
+        if memory_length != platform.memory_size() {
+            return Err(Chip8Error::InvalidSaveState(
+                "memory size does not match the platform".to_string(),
+            ));
+        }
+        let memory = reader.bytes(memory_length)?;
+        let v_register = reader.bytes(16)?;
+        let index = reader.u16()?;
+        let pc = reader.u16()?;
+        let mut stack = [0u16; STACK_DEPTH + 1];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let sp = reader.u16()?;
+        if sp as usize >= stack.len() {
+            return Err(Chip8Error::InvalidSaveState(
+                "stack pointer out of range".to_string(),
+            ));
+        }
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let hires = reader.bool()?;
+        let planes = reader.u8()? & 0x03;
+        let gfx = reader.bytes(HIRES_WIDTH * HIRES_HEIGHT)?;
+        let key = reader.bytes(16)?;
+        let rpl_flags = reader.bytes(16)?;
+        let halted = reader.bool()?;
+        let has_audio_pattern = reader.bool()?;
+        let audio_pattern = reader.bytes(16)?;
+        let pitch = reader.u8()?;
+        let random_algorithm = random_from_byte(reader.u8()?)?;
+        let random_seed = reader.u64()?;
+        let random_state = reader.u64()?;
+
+        self.quirks = quirks;
+        self.platform = platform;
+        self.memory.clear();
+        self.memory.extend_from_slice(memory);
+        self.v_register.copy_from_slice(v_register);
+        self.I = index;
+        self.pc = pc;
+        self.stack = stack;
+        self.sp = sp;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.hires = hires;
+        self.planes = planes;
+        self.gfx.copy_from_slice(gfx);
+        for (pressed, byte) in self.key.iter_mut().zip(key) {
+            *pressed = *byte != 0;
+        }
+        self.rpl_flags.copy_from_slice(rpl_flags);
+        self.halted = halted;
+        self.audio_pattern = if has_audio_pattern {
+            let mut bits = [0u8; 16];
+            bits.copy_from_slice(audio_pattern);
+            Some(bits)
+        } else {
+            None
+        };
+        self.pitch = pitch;
+        self.random = Random::restore(random_algorithm, random_seed, random_state);
+        self.draw_flag = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running_chip8() -> Chip8 {
+        let mut chip8 = Chip8::init();
+        // draw the 0 from the font at 3,4 then loop forever adding to V2
+        chip8
+            .load_rom(&[
+                0xa0, 0x50, 0x60, 0x03, 0x61, 0x04, 0xd0, 0x15, 0x72, 0x01, 0x12, 0x08,
+            ])
+            .unwrap();
+        for _ in 0..10 {
+            chip8.step().unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut chip8 = running_chip8();
+        chip8.quirks = Quirks::COSMAC_VIP;
+        let state = chip8.save_state();
+        let v2 = chip8.v_register[2];
+        let gfx = chip8.gfx;
+        for _ in 0..10 {
+            chip8.step().unwrap();
+        }
+        chip8.quirks = Quirks::MODERN;
+        assert_ne!(chip8.v_register[2], v2);
+
+        chip8.load_state(&state).unwrap();
+        assert_eq!(chip8.v_register[2], v2);
+        assert_eq!(chip8.gfx[..], gfx[..]);
+        assert_eq!(chip8.quirks, Quirks::COSMAC_VIP);
+        assert_eq!(chip8.save_state(), state);
+    }
+
+    #[test]
+    fn load_state_other_rom() {
+        let chip8 = running_chip8();
+        let state = chip8.save_state();
+        let mut other = Chip8::init();
+        other.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(matches!(
+            other.load_state(&state),
+            Err(Chip8Error::SaveStateRomMismatch)
+        ));
+    }
+
+    #[test]
+    fn load_state_bad_data() {
+        let mut chip8 = running_chip8();
+        let mut state = chip8.save_state();
+        let pc = chip8.pc;
+        assert!(matches!(
+            chip8.load_state(&state[..state.len() - 1]),
+            Err(Chip8Error::InvalidSaveState(_))
+        ));
+        state[5] = 99;
+        assert!(matches!(
+            chip8.load_state(&state),
+            Err(Chip8Error::InvalidSaveState(_))
+        ));
+        assert!(matches!(
+            chip8.load_state(b"not a state"),
+            Err(Chip8Error::InvalidSaveState(_))
+        ));
+        assert_eq!(chip8.pc, pc);
+    }
+}
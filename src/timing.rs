@@ -0,0 +1,119 @@
+use crate::interperter::{Chip8, Opcode};
+
+// how long instructions took on the COSMAC VIP, in 1802 machine cycles of 8 clocks.
+// the numbers follow the paths through the original interpreter: every instruction
+// pays for the fetch and dispatch, then for its own routine, and the ones that loop
+// (clearing, drawing, bcd, register loads and stores) pay per iteration. they are
+// close enough for games that were tuned by eye on real hardware, not to the cycle
+// for every corner case
+
+// 1.7609 MHz divided into 8 clock machine cycles and 60 frames a second
+pub const VIP_CYCLES_PER_SECOND: u32 = 220_113;
+pub const VIP_FRAME_CYCLES: u32 = VIP_CYCLES_PER_SECOND / 60;
+// the 1861 video chip steals a cycle for each of the 8 bytes on its 128 scanlines,
+// and the interrupt routine that sets it up and ticks the timers runs every frame
+pub const VIP_DISPLAY_CYCLES: u32 = 128 * 8 + 46;
+// what is left for the interpreter each frame
+pub const VIP_CYCLES_PER_FRAME: u32 = VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES;
+
+const FETCH_CYCLES: u32 = 40;
+// a skip taken costs a little more than one that falls through
+pub(crate) const SKIP_CYCLES: u32 = 4;
+
+// the cost of the instruction about to run, before it changes anything. skips add
+// SKIP_CYCLES on top once it is known whether they skipped
+pub(crate) fn vip_cycles(chip8: &Chip8, opcode: &(Opcode, [u8; 2])) -> u32 {
+    let x = (opcode.1[0] & 0x0f) as usize;
+    let y = (opcode.1[1] >> 4) as usize;
+    let vx = chip8.v_register[x] as u32;
+    let index = chip8.index() as u32;
+    let routine = match opcode.0 {
+        // clears the 256 display bytes three instructions at a time
+        Opcode::CLS => 24 + 256 * 6,
+        Opcode::RET => 10,
+        // machine code routines are not run, only dispatched
+        Opcode::SYS => 0,
+        Opcode::JP_A => 12,
+        Opcode::CALL => 26,
+        Opcode::SE_VB | Opcode::SNE_VB => 10,
+        Opcode::SE_VV | Opcode::SNE_VV => 14,
+        Opcode::LD_VB => 6,
+        Opcode::ADD_VB => 10,
+        // the 8XYN group builds and runs a small routine on the stack
+        Opcode::LD_VV
+        | Opcode::OR
+        | Opcode::AND
+        | Opcode::XOR
+        | Opcode::ADD_VV
+        | Opcode::SUB
+        | Opcode::SHR
+        | Opcode::SUBN
+        | Opcode::SHL => 44,
+        Opcode::LD_IA => 12,
+        Opcode::JP_VA => {
+            let target = (u16::from_be_bytes(opcode.1) & 0x0fff) as u32;
+            let crosses_page = (target & 0xff) + chip8.v_register[0] as u32 > 0xff;
+            22 + if crosses_page { 2 } else { 0 }
+        }
+        Opcode::RND => 36,
+        Opcode::DRW => draw_cycles(chip8, vx, chip8.v_register[y] as u32, opcode.1[1] & 0x0f),
+        Opcode::SKP | Opcode::SKNP => 14,
+        Opcode::LD_VDT | Opcode::LD_DTV | Opcode::LD_STV => 10,
+        // one pass of the wait loop, it runs again until a key comes
+        Opcode::LD_VK => 18,
+        Opcode::ADD_IV => {
+            let crosses_page = (index & 0xff) + vx > 0xff;
+            16 + if crosses_page { 4 } else { 0 }
+        }
+        Opcode::LD_FV => 16,
+        // each decimal digit is found by repeated subtraction
+        Opcode::LD_BV => 80 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10),
+        Opcode::LD_IV | Opcode::LD_VI => 14 + 14 * (x as u32 + 1),
+        // the super chip and xo chip additions never ran on a VIP, they only pay the
+        // fetch so they still use up the frame
+        _ => 0,
+    };
+    FETCH_CYCLES + routine
+}
+
+fn draw_cycles(chip8: &Chip8, x: u32, y: u32, rows: u8) -> u32 {
+    let (width, height) = chip8.resolution();
+    let x = x % width as u32;
+    let y = y % height as u32;
+    let mut rows = rows as u32;
+    if chip8.quirks.clip_sprites {
+        rows = rows.min(height as u32 - y);
+    }
+    // every row is shifted into place a bit at a time, and a sprite that is not
+    // byte aligned gets xored into two display bytes instead of one
+    let shift = x % 8;
+    let per_row = 34 + 4 * shift + if shift != 0 { 16 } else { 0 };
+    26 + rows * per_row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interperter::decode_opcode;
+
+    fn cycles(chip8: &Chip8, bytes: [u8; 2]) -> u32 {
+        vip_cycles(chip8, &decode_opcode(bytes).unwrap())
+    }
+
+    #[test]
+    fn costs_depend_on_operands() {
+        let mut chip8 = Chip8::init();
+        assert_eq!(cycles(&chip8, [0x60, 0x01]), 46);
+        chip8.v_register[0] = 0;
+        chip8.v_register[1] = 5;
+        let aligned = cycles(&chip8, [0xd0, 0x15]);
+        chip8.v_register[0] = 3;
+        let shifted = cycles(&chip8, [0xd0, 0x15]);
+        assert_eq!(aligned, 40 + 26 + 5 * 34);
+        assert_eq!(shifted, 40 + 26 + 5 * (34 + 12 + 16));
+        assert!(cycles(&chip8, [0xd0, 0x1f]) > shifted);
+        chip8.v_register[2] = 199;
+        assert_eq!(cycles(&chip8, [0xf2, 0x33]), 40 + 80 + 16 * 19);
+        assert!(cycles(&chip8, [0xff, 0x65]) > cycles(&chip8, [0xf0, 0x65]));
+    }
+}
@@ -0,0 +1,167 @@
+// runs test roms headlessly and compares the final screen with a golden image for each
+// interpreter variant, so a change in execute_opcode that alters what a rom draws fails
+// cargo test.
+//
+// roms live in tests/roms and golden images in tests/golden/<variant>/<rom>.pbm. the
+// timendus suite is gpl licensed and not checked in, so community_suite is ignored
+// by default and cargo test lists it as such. tests/fetch-suite.sh downloads it, then
+// `cargo test -- --ignored` runs it and fails on any rom or golden image that is
+// missing. its golden images are not checked in yet, so ci leaves it out until they
+// are. set CHIP8_BLESS=1 to write the golden images from the current output, then
+// look at every one of them before checking it in.
+use chip8::display::{terminal_frame, write_pbm};
+use chip8::input::ScriptedKeypad;
+use chip8::{Chip8, Platform, Quirks};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+struct Case {
+    rom: &'static str,
+    variant: &'static str,
+    platform: Platform,
+    quirks: Quirks,
+    // upper limit, a case stops early once the rom halts or jumps to itself
+    frames: u64,
+    instructions_per_frame: u32,
+    keys: &'static str,
+    // the timendus suite reads its menu choice from 0x1ff when it is set, so the
+    // quirks and keypad tests can pick a platform or test without any key presses
+    menu_choice: Option<u8>,
+}
+
+const fn case(rom: &'static str, variant: &'static str, quirks: Quirks) -> Case {
+    Case {
+        rom,
+        variant,
+        platform: Platform::Chip8,
+        quirks,
+        frames: 120,
+        instructions_per_frame: 1000,
+        keys: "",
+        menu_choice: None,
+    }
+}
+
+const CASES: &[Case] = &[
+    case("timers.ch8", "modern", Quirks::MODERN),
+    case("memory.ch8", "modern", Quirks::MODERN),
+    case("memory.ch8", "vip", Quirks::COSMAC_VIP),
+    case("memory.ch8", "chip48", Quirks::CHIP_48),
+    case("drawing.ch8", "modern", Quirks::MODERN),
+    case("drawing.ch8", "vip", Quirks::COSMAC_VIP),
+];
+
+// https://github.com/Timendus/chip8-test-suite, fetched by tests/fetch-suite.sh
+const SUITE_CASES: &[Case] = &[
+    case("1-chip8-logo.ch8", "modern", Quirks::MODERN),
+    case("2-ibm-logo.ch8", "modern", Quirks::MODERN),
+    case("3-corax+.ch8", "modern", Quirks::MODERN),
+    case("4-flags.ch8", "modern", Quirks::MODERN),
+    case("4-flags.ch8", "vip", Quirks::COSMAC_VIP),
+    Case {
+        menu_choice: Some(1),
+        ..case("5-quirks.ch8", "vip", Quirks::COSMAC_VIP)
+    },
+    Case {
+        platform: Platform::SuperChip,
+        menu_choice: Some(2),
+        ..case("5-quirks.ch8", "schip", Quirks::SUPER_CHIP)
+    },
+    Case {
+        platform: Platform::XoChip,
+        menu_choice: Some(3),
+        ..case("5-quirks.ch8", "xochip", Quirks::XO_CHIP)
+    },
+    // the FX0A test, pressing and releasing 5 once
+    Case {
+        menu_choice: Some(3),
+        keys: "30 down 5\n40 up 5\n",
+        instructions_per_frame: 15,
+        ..case("6-keypad.ch8", "modern", Quirks::MODERN)
+    },
+];
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn run_case(case: &Case, rom: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::init();
+    chip8.set_platform(case.platform);
+    chip8.quirks = case.quirks;
+    chip8.set_random_seed(0);
+    chip8.load_rom(rom).unwrap();
+    if let Some(choice) = case.menu_choice {
+        chip8.memory_mut()[0x1ff] = choice;
+    }
+    let mut keypad = ScriptedKeypad::parse(case.keys).unwrap();
+    for frame in 0..case.frames {
+        if chip8.is_halted() || (chip8.is_spinning() && keypad.is_finished()) {
+            break;
+        }
+        if let Err(error) = chip8.run_frame(&mut keypad, case.instructions_per_frame) {
+            panic!("{} ({}) frame {}: {}", case.rom, case.variant, frame, error);
+        }
+    }
+    chip8
+}
+
+#[test]
+fn conformance() {
+    let failures = check(CASES);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "needs the timendus suite, run tests/fetch-suite.sh first"]
+fn community_suite() {
+    let failures = check(SUITE_CASES);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// runs every case and returns what went wrong, a missing rom included
+fn check(cases: &[Case]) -> Vec<String> {
+    let bless = env::var_os("CHIP8_BLESS").is_some();
+    let mut failures = Vec::new();
+    for case in cases {
+        let rom_path = tests_dir().join("roms").join(case.rom);
+        let rom = match fs::read(&rom_path) {
+            Ok(rom) => rom,
+            Err(error) => {
+                failures.push(format!("{}: {}", rom_path.display(), error));
+                continue;
+            }
+        };
+
+        let chip8 = run_case(case, &rom);
+        let (width, height) = chip8.resolution();
+        let mut actual = Vec::new();
+        write_pbm(&mut actual, chip8.framebuffer(), width, height).unwrap();
+
+        let golden_path = tests_dir()
+            .join("golden")
+            .join(case.variant)
+            .join(Path::new(case.rom).with_extension("pbm"));
+        if bless {
+            fs::create_dir_all(golden_path.parent().unwrap()).unwrap();
+            fs::write(&golden_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read(&golden_path) {
+            Ok(golden) if golden == actual => {}
+            Ok(_) => failures.push(format!(
+                "{} ({}) does not match {}, it drew:\n{}",
+                case.rom,
+                case.variant,
+                golden_path.display(),
+                terminal_frame(chip8.framebuffer(), width, height)
+            )),
+            Err(_) => failures.push(format!(
+                "{} ({}) has no golden image, run with CHIP8_BLESS=1 to make one",
+                case.rom, case.variant
+            )),
+        }
+    }
+    failures
+}
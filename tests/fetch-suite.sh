@@ -0,0 +1,10 @@
+#!/bin/sh
+# downloads the timendus chip8 test suite roms into tests/roms for the ignored
+# community_suite test, then run: cargo test --test conformance -- --ignored
+set -eu
+version="${CHIP8_SUITE_VERSION:-main}"
+base="https://github.com/Timendus/chip8-test-suite/raw/$version/bin"
+roms="$(dirname "$0")/roms"
+for rom in 1-chip8-logo.ch8 2-ibm-logo.ch8 3-corax+.ch8 4-flags.ch8 5-quirks.ch8 6-keypad.ch8; do
+    curl --fail --silent --show-error --location --output "$roms/$rom" "$base/$rom"
+done